@@ -2,8 +2,13 @@ use std::{borrow::Cow, sync::Arc, time::Duration};
 
 use cookie_monster::{Cookie, CookieBuilder, SameSite};
 
-use crate::cookie::{
-    CookieContext, CookieContextInner, CookieStore, expiry::SessionExpiry, store::ErasedStore,
+use http::Method;
+
+use crate::{
+    cookie::{
+        CookieContext, CookieContextInner, CookieStore, expiry::SessionExpiry, store::ErasedStore,
+    },
+    utils::enforce::Enforcement,
 };
 
 static DEFAULT_SESSION_COOKIE_NAME: &str = "session";
@@ -13,6 +18,7 @@ pub struct CookieSessionBuilder<S> {
     store: S,
     pub(crate) cookie_opts: CookieOptionsBuilder,
     pub(crate) expiry: Option<SessionExpiry>,
+    enforcement: Enforcement,
 }
 
 pub(crate) struct CookieOptionsBuilder {
@@ -73,6 +79,7 @@ impl CookieSessionBuilder<()> {
             store: (),
             cookie_opts: CookieOptionsBuilder::new(),
             expiry: None,
+            enforcement: Enforcement::default(),
         }
     }
 }
@@ -112,11 +119,41 @@ impl<S> CookieSessionBuilder<S> {
         self
     }
 
+    /// Reject requests without a valid session with a `401 Unauthorized` in the layer, before
+    /// they reach the handler. Exempt paths are relative to the router the layer is added to,
+    /// so the prefix of a `nest`ed router is not part of them.
+    pub fn require_session(mut self) -> Self {
+        self.enforcement.enable();
+        self
+    }
+
+    /// Don't require a session for requests to `path`, when
+    /// [`require_session`](Self::require_session) is used.
+    pub fn exempt_path(mut self, path: impl Into<Cow<'static, str>>) -> Self {
+        self.enforcement.exempt_path(path);
+        self
+    }
+
+    /// Don't require a session for requests to `prefix` and the paths below it, when
+    /// [`require_session`](Self::require_session) is used.
+    pub fn exempt_path_prefix(mut self, prefix: impl Into<Cow<'static, str>>) -> Self {
+        self.enforcement.exempt_path_prefix(prefix);
+        self
+    }
+
+    /// Don't require a session for requests with this method, when
+    /// [`require_session`](Self::require_session) is used.
+    pub fn exempt_method(mut self, method: Method) -> Self {
+        self.enforcement.exempt_method(method);
+        self
+    }
+
     pub fn store<S1>(self, store: S1) -> CookieSessionBuilder<S1> {
         CookieSessionBuilder {
             store,
             cookie_opts: self.cookie_opts,
             expiry: self.expiry,
+            enforcement: self.enforcement,
         }
     }
 }
@@ -148,6 +185,7 @@ impl<S> CookieSessionBuilder<S> {
         CookieContext(Arc::new(CookieContextInner {
             store,
             cookie_opts,
+            enforcement: self.enforcement,
             handle,
         }))
     }
@@ -9,7 +9,7 @@ use std::{borrow::Cow, convert::Infallible, error::Error, sync::Arc};
 
 use axum::{
     extract::{FromRef, FromRequestParts},
-    http::{HeaderMap, request::Parts},
+    http::{HeaderMap, Method, Uri, request::Parts},
 };
 pub(crate) use builder::CookieOptionsBuilder;
 pub use builder::CookieSessionBuilder;
@@ -22,7 +22,7 @@ use tokio::task::JoinHandle;
 
 use crate::{
     cookie::store::{BoxDynError, ErasedStore},
-    utils::utc_now,
+    utils::{enforce::Enforcement, utc_now},
 };
 
 pub struct CookieContext<S>(Arc<CookieContextInner<S>>);
@@ -30,6 +30,7 @@ pub struct CookieContext<S>(Arc<CookieContextInner<S>>);
 struct CookieContextInner<S> {
     store: ErasedStore<S>,
     cookie_opts: CookieBuilder,
+    enforcement: Enforcement,
     handle: Option<JoinHandle<()>>,
 }
 
@@ -83,6 +84,11 @@ impl<S: 'static> CookieContext<S> {
         self.0.store.remove_session(session_id).await
     }
 
+    #[cfg(feature = "oauth2")]
+    pub(crate) async fn store_session(&self, session: CookieSession<S>) -> Result<(), BoxDynError> {
+        self.0.store.store_session(session).await
+    }
+
     pub fn build_cookie(&self, name: impl Into<Cow<'static, str>>) -> CookieBuilder {
         self.0.cookie_opts.clone().name(name)
     }
@@ -95,6 +101,10 @@ impl<S: 'static> CookieContext<S> {
         self.0.store.remove_before(deadline).await
     }
 
+    pub(crate) fn rejects_missing_session(&self, method: &Method, uri: &Uri) -> bool {
+        self.0.enforcement.rejects(method, uri)
+    }
+
     pub(crate) async fn load_from_headers(
         &self,
         headers: &HeaderMap,
@@ -38,7 +38,11 @@ where
                 Ok(Some(session)) => {
                     req.extensions_mut().insert(session);
                 }
-                Ok(None) => {}
+                Ok(None) => {
+                    if this.inner.rejects_missing_session(req.method(), req.uri()) {
+                        return Ok(StatusCode::UNAUTHORIZED.into_response());
+                    }
+                }
                 Err(_) => return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response()),
             }
 
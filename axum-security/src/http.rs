@@ -1,5 +1,9 @@
 use oauth2::reqwest::{Client, redirect::Policy};
 
+/// The `reqwest` version used for requests to providers, for example by
+/// [`ClientCredentials`](crate::oauth2::ClientCredentials).
+pub use oauth2::reqwest;
+
 pub fn default_reqwest_client() -> Client {
     ::oauth2::reqwest::Client::builder()
         .redirect(Policy::none())
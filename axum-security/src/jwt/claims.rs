@@ -0,0 +1,255 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::utils::utc_now_secs;
+
+/// A jwt payload with the registered claims from RFC 7519 next to the user data.
+///
+/// The user data is flattened into the same json object, so `T` should not contain any of the
+/// registered claim names itself.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Claims<T> {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<Audience>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exp: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nbf: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cnf: Option<Confirmation>,
+    #[serde(flatten)]
+    pub data: T,
+}
+
+impl<T> Claims<T> {
+    pub fn new(data: T) -> Self {
+        Self {
+            iss: None,
+            sub: None,
+            aud: None,
+            exp: None,
+            nbf: None,
+            iat: None,
+            jti: None,
+            cnf: None,
+            data,
+        }
+    }
+
+    pub fn subject(mut self, sub: impl Into<String>) -> Self {
+        self.sub = Some(sub.into());
+        self
+    }
+
+    pub fn expires_at(mut self, exp: u64) -> Self {
+        self.exp = Some(exp);
+        self
+    }
+
+    /// Binds the token to the key with this JWK SHA-256 thumbprint, see RFC 9449.
+    pub fn bind_to(mut self, jkt: impl Into<String>) -> Self {
+        self.cnf = Some(Confirmation {
+            jkt: Some(jkt.into()),
+        });
+        self
+    }
+
+    pub(crate) fn fill(&mut self, registered: &RegisteredClaims) {
+        let now = utc_now_secs();
+
+        if self.iss.is_none() {
+            self.iss = registered.issuer.clone();
+        }
+
+        if self.aud.is_none() {
+            self.aud = registered.audience.clone().map(Audience::Single);
+        }
+
+        if self.exp.is_none() {
+            self.exp = registered.ttl.map(|ttl| now + ttl.as_secs());
+        }
+
+        self.iat.get_or_insert(now);
+        self.nbf.get_or_insert(now);
+        self.jti
+            .get_or_insert_with(|| Uuid::now_v7().as_simple().to_string());
+    }
+}
+
+/// The `cnf` claim from RFC 7800, which binds a token to a key.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Confirmation {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jkt: Option<String>,
+}
+
+/// The `aud` claim, which can either be a single string or an array of strings.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Audience {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl Audience {
+    pub fn contains(&self, audience: &str) -> bool {
+        match self {
+            Audience::Single(aud) => aud == audience,
+            Audience::Multiple(auds) => auds.iter().any(|aud| aud == audience),
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct RegisteredClaims {
+    pub(crate) issuer: Option<String>,
+    pub(crate) audience: Option<String>,
+    pub(crate) ttl: Option<Duration>,
+}
+
+#[cfg(test)]
+mod jwt_claims {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        jwt::{Audience, Claims, JwtContext, JwtErrorKind, Validation},
+        utils::utc_now_secs,
+    };
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    struct User {
+        name: String,
+    }
+
+    fn user() -> User {
+        User {
+            name: "admin".into(),
+        }
+    }
+
+    #[test]
+    fn issue() {
+        let context = JwtContext::builder()
+            .jwt_secret("test")
+            .issuer("axum-security")
+            .audience("tests")
+            .ttl(Duration::from_secs(60))
+            .build::<Claims<User>>();
+
+        let token = context.issue(user()).unwrap();
+        let claims = context.decode(token).unwrap().claims;
+        let now = utc_now_secs();
+
+        assert!(claims.data.name == "admin");
+        assert!(claims.iss.as_deref() == Some("axum-security"));
+        assert!(claims.aud == Some(Audience::Single("tests".into())));
+        assert!(claims.iat.is_some_and(|iat| iat <= now));
+        assert!(claims.nbf == claims.iat);
+        assert!(claims.exp.is_some_and(|exp| exp > now && exp <= now + 60));
+        assert!(claims.jti.is_some());
+    }
+
+    #[test]
+    fn issue_without_ttl() {
+        let context = JwtContext::builder()
+            .jwt_secret("test")
+            .build::<Claims<User>>();
+
+        let err = context.issue(user()).unwrap_err();
+        assert!(matches!(err.kind(), JwtErrorKind::MissingRequiredClaim(claim) if claim == "exp"));
+
+        let claims = Claims::new(user()).expires_at(utc_now_secs() + 60);
+        let token = context.issue_claims(claims).unwrap();
+        assert!(context.decode(token).unwrap().claims.data.name == "admin");
+
+        let mut validation = Validation::default();
+        validation.required_spec_claims.clear();
+        validation.validate_exp = false;
+
+        let context = JwtContext::builder()
+            .jwt_secret("test")
+            .validation(validation)
+            .build::<Claims<User>>();
+
+        let token = context.issue(user()).unwrap();
+        let claims = context.decode(token).unwrap().claims;
+        assert!(claims.exp.is_none());
+    }
+
+    #[test]
+    fn keeps_explicit_claims() {
+        let context = JwtContext::builder()
+            .jwt_secret("test")
+            .ttl(Duration::from_secs(60))
+            .build::<Claims<User>>();
+
+        let exp = utc_now_secs() + 1000;
+        let claims = Claims::new(user()).subject("user-1").expires_at(exp);
+
+        let token = context.issue_claims(claims).unwrap();
+        let claims = context.decode(token).unwrap().claims;
+
+        assert!(claims.sub.as_deref() == Some("user-1"));
+        assert!(claims.exp == Some(exp));
+    }
+
+    #[test]
+    fn enforces_issuer_and_audience() {
+        let issuer = JwtContext::builder()
+            .jwt_secret("test")
+            .issuer("someone-else")
+            .audience("tests")
+            .ttl(Duration::from_secs(60))
+            .build::<Claims<User>>();
+
+        let token = issuer.issue(user()).unwrap();
+
+        let verifier = JwtContext::builder()
+            .jwt_secret("test")
+            .issuer("axum-security")
+            .audience("tests")
+            .build::<Claims<User>>();
+
+        let err = verifier.decode(&token).unwrap_err();
+        assert!(matches!(err.kind(), JwtErrorKind::InvalidIssuer));
+
+        let verifier = JwtContext::builder()
+            .jwt_secret("test")
+            .issuer("someone-else")
+            .audience("other-service")
+            .build::<Claims<User>>();
+
+        let err = verifier.decode(&token).unwrap_err();
+        assert!(matches!(err.kind(), JwtErrorKind::InvalidAudience));
+    }
+
+    #[test]
+    fn audience_array() {
+        let context = JwtContext::builder()
+            .jwt_secret("test")
+            .audience("b")
+            .build::<Claims<User>>();
+
+        let mut claims = Claims::new(user()).expires_at(utc_now_secs() + 1000);
+        claims.aud = Some(Audience::Multiple(vec!["a".into(), "b".into()]));
+
+        let token = context.issue_claims(claims).unwrap();
+        let aud = context.decode(token).unwrap().claims.aud.unwrap();
+
+        assert!(aud.contains("a"));
+        assert!(aud.contains("b"));
+        assert!(!aud.contains("c"));
+    }
+}
@@ -0,0 +1,69 @@
+use jsonwebtoken::{
+    DecodingKey, EncodingKey, Header, TokenData, Validation, decode, encode, errors::ErrorKind,
+};
+use serde::{Serialize, de::DeserializeOwned};
+
+#[cfg(feature = "jwe")]
+use crate::jwt::JweKey;
+use crate::jwt::JwtError;
+
+/// How tokens are turned into strings and back.
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum Codec {
+    Signed {
+        /// Missing for contexts that only verify tokens.
+        encoding_key: Option<EncodingKey>,
+        decoding_key: DecodingKey,
+        header: Header,
+    },
+    #[cfg(feature = "jwe")]
+    Encrypted(JweKey),
+}
+
+impl Codec {
+    pub(crate) fn encode<T: Serialize>(&self, data: &T) -> Result<String, JwtError> {
+        match self {
+            Codec::Signed {
+                encoding_key,
+                header,
+                ..
+            } => match encoding_key {
+                Some(encoding_key) => encode(header, data, encoding_key),
+                None => Err(ErrorKind::InvalidKeyFormat.into()),
+            },
+            #[cfg(feature = "jwe")]
+            Codec::Encrypted(key) => {
+                let payload = serde_json::to_vec(data).map_err(json_error)?;
+                key.encrypt(&payload)
+            }
+        }
+    }
+
+    pub(crate) fn decode<T: DeserializeOwned>(
+        &self,
+        token: &[u8],
+        validation: &Validation,
+    ) -> Result<TokenData<T>, JwtError> {
+        match self {
+            Codec::Signed { decoding_key, .. } => decode(token, decoding_key, validation),
+            #[cfg(feature = "jwe")]
+            Codec::Encrypted(key) => {
+                use serde_json::{Map, Value};
+
+                let (header, payload) = key.decrypt(token)?;
+
+                let claims: Map<String, Value> =
+                    serde_json::from_slice(&payload).map_err(json_error)?;
+                super::jwe::validate(&claims, validation)?;
+
+                let claims = serde_json::from_value(Value::Object(claims)).map_err(json_error)?;
+                Ok(TokenData { header, claims })
+            }
+        }
+    }
+}
+
+#[cfg(feature = "jwe")]
+fn json_error(e: serde_json::Error) -> JwtError {
+    ErrorKind::Json(std::sync::Arc::new(e)).into()
+}
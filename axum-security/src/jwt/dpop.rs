@@ -0,0 +1,398 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::Infallible,
+    str::FromStr,
+    sync::Mutex,
+    time::Duration,
+};
+
+use axum::extract::{FromRequestParts, OptionalFromRequestParts};
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use http::{Extensions, HeaderValue, StatusCode, request::Parts};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode, jwk::Jwk};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::utils::utc_now_secs;
+
+pub(crate) static DPOP_HEADER: &str = "DPoP";
+pub(crate) static PREFIX_DPOP: &str = "DPoP ";
+
+/// Algorithms that are accepted for DPoP proofs, only asymmetric algorithms are allowed.
+static ALGORITHMS: &[Algorithm] = &[
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+];
+
+/// A validated DPoP proof, added to the request extensions next to the [`Jwt`](crate::jwt::Jwt).
+#[derive(Clone, Debug)]
+pub struct DpopProof {
+    /// The public key of the client.
+    pub jwk: Jwk,
+    /// The SHA-256 thumbprint of `jwk` (RFC 7638), this is the `cnf.jkt` claim of the access token.
+    pub jkt: String,
+    pub jti: String,
+    pub iat: u64,
+}
+
+impl DpopProof {
+    pub fn from_extensions(extensions: &mut Extensions) -> Option<Self> {
+        extensions.remove()
+    }
+}
+
+impl<S> FromRequestParts<S> for DpopProof
+where
+    S: Send + Sync,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        DpopProof::from_extensions(&mut parts.extensions).ok_or(StatusCode::UNAUTHORIZED)
+    }
+}
+
+impl<S> OptionalFromRequestParts<S> for DpopProof
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        Ok(DpopProof::from_extensions(&mut parts.extensions))
+    }
+}
+
+/// Why a request was rejected, used as the `error` of the `WWW-Authenticate` challenge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DpopError {
+    InvalidToken,
+    InvalidProof,
+}
+
+impl DpopError {
+    pub(crate) fn code(self) -> &'static str {
+        match self {
+            DpopError::InvalidToken => "invalid_token",
+            DpopError::InvalidProof => "invalid_dpop_proof",
+        }
+    }
+}
+
+pub(crate) struct Dpop {
+    max_age: Duration,
+    leeway: Duration,
+    origin: String,
+    replay: ReplayCache,
+}
+
+#[derive(Deserialize)]
+struct ProofClaims {
+    jti: String,
+    htm: String,
+    htu: String,
+    iat: u64,
+    ath: Option<String>,
+}
+
+impl Dpop {
+    pub(crate) fn new(max_age: Duration, leeway: Duration, origin: String) -> Self {
+        Self {
+            max_age,
+            leeway,
+            origin,
+            replay: ReplayCache::default(),
+        }
+    }
+
+    /// Validates the `DPoP` header of the request and checks that the access token with claims
+    /// `claims` is bound to the key of the proof.
+    pub(crate) fn verify(
+        &self,
+        parts: &Parts,
+        access_token: &str,
+        claims: &Map<String, Value>,
+    ) -> Result<DpopProof, DpopError> {
+        let mut headers = parts.headers.get_all(DPOP_HEADER).iter();
+
+        let (Some(proof), None) = (headers.next(), headers.next()) else {
+            tracing::debug!("request does not contain exactly one dpop proof");
+            return Err(DpopError::InvalidProof);
+        };
+
+        let proof = proof.to_str().map_err(|_| DpopError::InvalidProof)?;
+        let (jwk, jkt, claims_of_proof) = self.decode_proof(proof)?;
+
+        if claims_of_proof.htm != parts.method.as_str() {
+            tracing::debug!("dpop proof htm does not match the request method");
+            return Err(DpopError::InvalidProof);
+        }
+
+        let htu = claims_of_proof.htu.split(['?', '#']).next().unwrap_or("");
+        if normalize_url(htu).is_none_or(|htu| Some(htu) != self.request_url(parts)) {
+            tracing::debug!("dpop proof htu does not match the request url");
+            return Err(DpopError::InvalidProof);
+        }
+
+        let now = utc_now_secs();
+        let leeway = self.leeway.as_secs();
+        let max_age = self.max_age.as_secs();
+
+        if claims_of_proof.iat > now + leeway || claims_of_proof.iat + max_age + leeway < now {
+            tracing::debug!("dpop proof is expired or issued in the future");
+            return Err(DpopError::InvalidProof);
+        }
+
+        let ath = BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(access_token));
+        if claims_of_proof.ath.as_deref() != Some(ath.as_str()) {
+            tracing::debug!("dpop proof ath does not match the access token");
+            return Err(DpopError::InvalidProof);
+        }
+
+        let bound_to = claims
+            .get("cnf")
+            .and_then(|cnf| cnf.get("jkt"))
+            .and_then(Value::as_str);
+
+        if bound_to != Some(jkt.as_str()) {
+            tracing::debug!("access token is not bound to the dpop key");
+            return Err(DpopError::InvalidToken);
+        }
+
+        let expires_at = claims_of_proof.iat + max_age + leeway;
+        if !self.replay.insert(&claims_of_proof.jti, expires_at, now) {
+            tracing::debug!("dpop proof is replayed");
+            return Err(DpopError::InvalidProof);
+        }
+
+        Ok(DpopProof {
+            jwk,
+            jkt,
+            jti: claims_of_proof.jti,
+            iat: claims_of_proof.iat,
+        })
+    }
+
+    /// Returns the key, its thumbprint and the claims of the proof.
+    fn decode_proof(&self, proof: &str) -> Result<(Jwk, String, ProofClaims), DpopError> {
+        let header = proof.split('.').next().unwrap_or("");
+        let header = BASE64_URL_SAFE_NO_PAD
+            .decode(header)
+            .map_err(|_| DpopError::InvalidProof)?;
+        let header: Map<String, Value> =
+            serde_json::from_slice(&header).map_err(|_| DpopError::InvalidProof)?;
+
+        if !header
+            .get("typ")
+            .and_then(Value::as_str)
+            .is_some_and(|typ| typ.eq_ignore_ascii_case("dpop+jwt"))
+        {
+            return Err(DpopError::InvalidProof);
+        }
+
+        let algorithm = header
+            .get("alg")
+            .and_then(Value::as_str)
+            .and_then(|alg| Algorithm::from_str(alg).ok())
+            .filter(|alg| ALGORITHMS.contains(alg))
+            .ok_or(DpopError::InvalidProof)?;
+
+        let jwk = header
+            .get("jwk")
+            .and_then(Value::as_object)
+            .ok_or(DpopError::InvalidProof)?;
+
+        // The proof must only contain the public key, private parameters are rejected.
+        if ["d", "p", "q", "dp", "dq", "qi", "k"]
+            .iter()
+            .any(|param| jwk.contains_key(*param))
+        {
+            return Err(DpopError::InvalidProof);
+        }
+
+        let jkt = thumbprint(jwk).ok_or(DpopError::InvalidProof)?;
+        let jwk: Jwk = serde_json::from_value(Value::Object(jwk.clone()))
+            .map_err(|_| DpopError::InvalidProof)?;
+
+        let key = DecodingKey::from_jwk(&jwk).map_err(|_| DpopError::InvalidProof)?;
+
+        let mut validation = Validation::new(algorithm);
+        validation.required_spec_claims.clear();
+        validation.validate_exp = false;
+
+        let claims = decode::<ProofClaims>(proof, &key, &validation)
+            .map_err(|_| DpopError::InvalidProof)?
+            .claims;
+
+        Ok((jwk, jkt, claims))
+    }
+
+    /// The url of the request without query and fragment, which is compared with the `htu`
+    /// claim. The scheme and host come from the configured origin, never from the request, as
+    /// the `Host` header is chosen by the client.
+    fn request_url(&self, parts: &Parts) -> Option<String> {
+        normalize_url(&format!("{}{}", self.origin, parts.uri.path()))
+    }
+
+    /// The `WWW-Authenticate` header that is added to `401 Unauthorized` responses.
+    pub(crate) fn challenge(&self, error: Option<&str>) -> HeaderValue {
+        let algs = ALGORITHMS
+            .iter()
+            .map(|alg| format!("{alg:?}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let challenge = match error {
+            Some(error) => format!("DPoP error=\"{error}\", algs=\"{algs}\""),
+            None => format!("DPoP algs=\"{algs}\""),
+        };
+
+        HeaderValue::from_str(&challenge).expect("challenge is a valid header value")
+    }
+}
+
+/// Lowercases the scheme and host and removes the default port, so urls that only differ in
+/// those parts compare equal (RFC 3986, section 6.2.2 and 6.2.3).
+fn normalize_url(url: &str) -> Option<String> {
+    let (scheme, rest) = url.split_once("://")?;
+    let scheme = scheme.to_ascii_lowercase();
+
+    let (authority, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    };
+
+    let authority = authority.to_ascii_lowercase();
+    let authority = match scheme.as_str() {
+        "https" => authority.strip_suffix(":443").unwrap_or(&authority),
+        "http" => authority.strip_suffix(":80").unwrap_or(&authority),
+        _ => &authority,
+    };
+
+    if authority.is_empty() {
+        return None;
+    }
+
+    Some(format!("{scheme}://{authority}{path}"))
+}
+
+/// The SHA-256 JWK thumbprint from RFC 7638, computed over the required members of the key in
+/// lexicographic order.
+fn thumbprint(jwk: &Map<String, Value>) -> Option<String> {
+    let members: &[&str] = match jwk.get("kty")?.as_str()? {
+        "EC" => &["crv", "kty", "x", "y"],
+        "RSA" => &["e", "kty", "n"],
+        "OKP" => &["crv", "kty", "x"],
+        _ => return None,
+    };
+
+    let mut required = BTreeMap::new();
+    for member in members {
+        required.insert(*member, jwk.get(*member)?.as_str()?);
+    }
+
+    let json = serde_json::to_string(&required).ok()?;
+    Some(BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(json)))
+}
+
+/// Remembers the `jti` of every accepted proof until the proof expires.
+#[derive(Default)]
+struct ReplayCache(Mutex<ReplayCacheInner>);
+
+#[derive(Default)]
+struct ReplayCacheInner {
+    seen: HashMap<String, u64>,
+    last_purge: u64,
+}
+
+impl ReplayCache {
+    /// Returns false if `jti` was already seen.
+    fn insert(&self, jti: &str, expires_at: u64, now: u64) -> bool {
+        let mut inner = self.0.lock().unwrap();
+
+        if inner.last_purge + 60 < now {
+            inner.seen.retain(|_, expires_at| *expires_at >= now);
+            inner.last_purge = now;
+        }
+
+        match inner.seen.get(jti) {
+            Some(seen_until) if *seen_until >= now => false,
+            _ => {
+                inner.seen.insert(jti.to_string(), expires_at);
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod dpop_test {
+    use serde_json::json;
+
+    use crate::jwt::dpop::{ReplayCache, normalize_url, thumbprint};
+
+    #[test]
+    fn rfc7638_thumbprint() {
+        // The example key from RFC 7638, section 3.1.
+        let jwk = json!({
+            "kty": "RSA",
+            "n": "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw",
+            "e": "AQAB",
+            "alg": "RS256",
+            "kid": "2011-04-29"
+        });
+
+        assert_eq!(
+            thumbprint(jwk.as_object().unwrap()).as_deref(),
+            Some("NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs")
+        );
+
+        let jwk = json!({ "kty": "oct", "k": "c2VjcmV0" });
+        assert_eq!(thumbprint(jwk.as_object().unwrap()), None);
+    }
+
+    #[test]
+    fn normalized_urls() {
+        let normalized = Some("https://api.example.com/token".to_string());
+
+        assert_eq!(normalize_url("https://api.example.com/token"), normalized);
+        assert_eq!(normalize_url("HTTPS://API.Example.com/token"), normalized);
+        assert_eq!(
+            normalize_url("https://api.example.com:443/token"),
+            normalized
+        );
+        assert_eq!(
+            normalize_url("http://api.example.com:80"),
+            Some("http://api.example.com/".to_string())
+        );
+        assert_eq!(
+            normalize_url("https://api.example.com:8443/token"),
+            Some("https://api.example.com:8443/token".to_string())
+        );
+        // The path is case sensitive.
+        assert_ne!(normalize_url("https://api.example.com/Token"), normalized);
+        assert_eq!(normalize_url("api.example.com/token"), None);
+        assert_eq!(normalize_url("https:///token"), None);
+    }
+
+    #[test]
+    fn replay_cache() {
+        let cache = ReplayCache::default();
+
+        assert!(cache.insert("a", 100, 10));
+        assert!(!cache.insert("a", 100, 20));
+        assert!(cache.insert("b", 100, 20));
+        assert!(cache.insert("a", 200, 101));
+    }
+}
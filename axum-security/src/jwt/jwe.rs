@@ -0,0 +1,451 @@
+use std::{borrow::Cow, sync::Arc};
+
+use aes_gcm::{
+    Aes256Gcm, Key, Nonce,
+    aead::{Aead, KeyInit, Payload},
+};
+use aes_kw::KekAes256;
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use jsonwebtoken::{
+    Header, Validation,
+    errors::{Error as JwtError, ErrorKind},
+    get_current_timestamp,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+const KEY_LEN: usize = 32;
+const IV_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+const ALG_DIR: &str = "dir";
+const ALG_A256KW: &str = "A256KW";
+const ENC_A256GCM: &str = "A256GCM";
+
+/// A key used to encrypt tokens with JWE compact serialization (RFC 7516).
+///
+/// The content is always encrypted with `A256GCM`, the key is either used directly (`dir`) or
+/// used to wrap a random content encryption key (`A256KW`). Only `typ` and `kid` are copied to
+/// the [`Header`] of decoded tokens.
+pub struct JweKey {
+    management: KeyManagement,
+    key: [u8; KEY_LEN],
+    kid: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum KeyManagement {
+    Direct,
+    A256Kw,
+}
+
+impl KeyManagement {
+    fn alg(self) -> &'static str {
+        match self {
+            KeyManagement::Direct => ALG_DIR,
+            KeyManagement::A256Kw => ALG_A256KW,
+        }
+    }
+}
+
+impl JweKey {
+    /// Use `key` directly as the content encryption key. The key has to be 32 bytes long.
+    pub fn dir(key: impl AsRef<[u8]>) -> Result<Self, JwtError> {
+        Self::new(KeyManagement::Direct, key.as_ref())
+    }
+
+    /// Use `key` to wrap a random content encryption key per token. The key has to be 32 bytes
+    /// long.
+    pub fn a256kw(key: impl AsRef<[u8]>) -> Result<Self, JwtError> {
+        Self::new(KeyManagement::A256Kw, key.as_ref())
+    }
+
+    /// Sets the `kid` header on encrypted tokens.
+    pub fn kid(mut self, kid: impl Into<String>) -> Self {
+        self.kid = Some(kid.into());
+        self
+    }
+
+    fn new(management: KeyManagement, key: &[u8]) -> Result<Self, JwtError> {
+        let key = key
+            .try_into()
+            .map_err(|_| JwtError::from(ErrorKind::InvalidKeyFormat))?;
+
+        Ok(Self {
+            management,
+            key,
+            kid: None,
+        })
+    }
+
+    pub(crate) fn encrypt(&self, payload: &[u8]) -> Result<String, JwtError> {
+        let header = JweHeader {
+            alg: self.management.alg().into(),
+            enc: ENC_A256GCM.into(),
+            typ: Some("JWT".into()),
+            kid: self.kid.as_deref().map(Cow::Borrowed),
+            zip: None,
+            crit: None,
+        };
+
+        let header = serde_json::to_vec(&header).map_err(json_error)?;
+        let header = BASE64_URL_SAFE_NO_PAD.encode(header);
+
+        let (cek, encrypted_key) = match self.management {
+            KeyManagement::Direct => (self.key, Vec::new()),
+            KeyManagement::A256Kw => {
+                let mut cek = [0u8; KEY_LEN];
+                rand::rng().fill_bytes(&mut cek);
+
+                let encrypted_key = KekAes256::from(self.key)
+                    .wrap_vec(&cek)
+                    .map_err(|_| JwtError::from(ErrorKind::InvalidKeyFormat))?;
+
+                (cek, encrypted_key)
+            }
+        };
+
+        let mut iv = [0u8; IV_LEN];
+        rand::rng().fill_bytes(&mut iv);
+
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&cek));
+        let mut ciphertext = cipher
+            .encrypt(
+                Nonce::from_slice(&iv),
+                Payload {
+                    msg: payload,
+                    aad: header.as_bytes(),
+                },
+            )
+            .map_err(|_| JwtError::from(ErrorKind::InvalidToken))?;
+
+        // aes-gcm appends the authentication tag to the ciphertext.
+        let tag = ciphertext.split_off(ciphertext.len() - TAG_LEN);
+
+        Ok(format!(
+            "{header}.{}.{}.{}.{}",
+            BASE64_URL_SAFE_NO_PAD.encode(encrypted_key),
+            BASE64_URL_SAFE_NO_PAD.encode(iv),
+            BASE64_URL_SAFE_NO_PAD.encode(ciphertext),
+            BASE64_URL_SAFE_NO_PAD.encode(tag),
+        ))
+    }
+
+    /// Decrypts `token`, returns the protected header and the plaintext.
+    pub(crate) fn decrypt(&self, token: &[u8]) -> Result<(Header, Vec<u8>), JwtError> {
+        let token = str::from_utf8(token).map_err(|_| JwtError::from(ErrorKind::InvalidToken))?;
+
+        let mut segments = token.split('.');
+        let (
+            Some(header_segment),
+            Some(encrypted_key),
+            Some(iv),
+            Some(ciphertext),
+            Some(tag),
+            None,
+        ) = (
+            segments.next(),
+            segments.next(),
+            segments.next(),
+            segments.next(),
+            segments.next(),
+            segments.next(),
+        )
+        else {
+            return Err(ErrorKind::InvalidToken.into());
+        };
+
+        let header = decode_segment(header_segment)?;
+        let header: JweHeader = serde_json::from_slice(&header).map_err(json_error)?;
+
+        if header.alg != self.management.alg() || header.enc != ENC_A256GCM {
+            return Err(ErrorKind::InvalidAlgorithm.into());
+        }
+
+        // Compression and critical extensions are not supported.
+        if header.zip.is_some() || header.crit.is_some() {
+            return Err(ErrorKind::InvalidToken.into());
+        }
+
+        let encrypted_key = decode_segment(encrypted_key)?;
+        let cek = match self.management {
+            KeyManagement::Direct => {
+                if !encrypted_key.is_empty() {
+                    return Err(ErrorKind::InvalidToken.into());
+                }
+                self.key
+            }
+            KeyManagement::A256Kw => {
+                let mut cek = [0u8; KEY_LEN];
+                KekAes256::from(self.key)
+                    .unwrap(&encrypted_key, &mut cek)
+                    .map_err(|_| JwtError::from(ErrorKind::InvalidSignature))?;
+                cek
+            }
+        };
+
+        let iv = decode_segment(iv)?;
+        if iv.len() != IV_LEN {
+            return Err(ErrorKind::InvalidToken.into());
+        }
+
+        let mut ciphertext = decode_segment(ciphertext)?;
+        ciphertext.extend_from_slice(&decode_segment(tag)?);
+
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&cek));
+        let plaintext = cipher
+            .decrypt(
+                Nonce::from_slice(&iv),
+                Payload {
+                    msg: &ciphertext,
+                    aad: header_segment.as_bytes(),
+                },
+            )
+            .map_err(|_| JwtError::from(ErrorKind::InvalidSignature))?;
+
+        let header = Header {
+            typ: header.typ.map(Cow::into_owned),
+            kid: header.kid.map(Cow::into_owned),
+            ..Header::default()
+        };
+
+        Ok((header, plaintext))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct JweHeader<'a> {
+    #[serde(borrow)]
+    alg: Cow<'a, str>,
+    #[serde(borrow)]
+    enc: Cow<'a, str>,
+    #[serde(default, borrow, skip_serializing_if = "Option::is_none")]
+    typ: Option<Cow<'a, str>>,
+    #[serde(default, borrow, skip_serializing_if = "Option::is_none")]
+    kid: Option<Cow<'a, str>>,
+    #[serde(default, borrow, skip_serializing_if = "Option::is_none")]
+    zip: Option<Cow<'a, str>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    crit: Option<Vec<String>>,
+}
+
+fn decode_segment(segment: &str) -> Result<Vec<u8>, JwtError> {
+    BASE64_URL_SAFE_NO_PAD
+        .decode(segment)
+        .map_err(|e| ErrorKind::Base64(e).into())
+}
+
+fn json_error(e: serde_json::Error) -> JwtError {
+    ErrorKind::Json(Arc::new(e)).into()
+}
+
+/// Validates the registered claims the same way `jsonwebtoken` does for signed tokens.
+pub(crate) fn validate(
+    claims: &Map<String, Value>,
+    validation: &Validation,
+) -> Result<(), JwtError> {
+    for required_claim in &validation.required_spec_claims {
+        if matches!(
+            required_claim.as_str(),
+            "exp" | "sub" | "iss" | "aud" | "nbf"
+        ) && !claims.contains_key(required_claim)
+        {
+            return Err(ErrorKind::MissingRequiredClaim(required_claim.clone()).into());
+        }
+    }
+
+    let now = get_current_timestamp();
+
+    if validation.validate_exp
+        && let Some(exp) = claims.get("exp")
+    {
+        let exp = numeric(exp).ok_or_else(|| ErrorKind::InvalidClaimFormat("exp".into()))?;
+
+        if exp < validation.reject_tokens_expiring_in_less_than {
+            return Err(ErrorKind::InvalidToken.into());
+        }
+
+        if exp - validation.reject_tokens_expiring_in_less_than < now - validation.leeway {
+            return Err(ErrorKind::ExpiredSignature.into());
+        }
+    }
+
+    if validation.validate_nbf
+        && let Some(nbf) = claims.get("nbf")
+    {
+        let nbf = numeric(nbf).ok_or_else(|| ErrorKind::InvalidClaimFormat("nbf".into()))?;
+
+        if nbf > now + validation.leeway {
+            return Err(ErrorKind::ImmatureSignature.into());
+        }
+    }
+
+    if let (Some(Value::String(sub)), Some(correct_sub)) = (claims.get("sub"), &validation.sub)
+        && sub != correct_sub
+    {
+        return Err(ErrorKind::InvalidSubject.into());
+    }
+
+    if let (Some(iss), Some(correct_iss)) = (claims.get("iss"), &validation.iss)
+        && !strings(iss).any(|iss| correct_iss.contains(iss))
+    {
+        return Err(ErrorKind::InvalidIssuer.into());
+    }
+
+    if validation.validate_aud
+        && let Some(aud) = claims.get("aud")
+    {
+        let valid = match &validation.aud {
+            Some(correct_aud) => strings(aud).any(|aud| correct_aud.contains(aud)),
+            // Tokens with an audience are only accepted if we know who we are.
+            None => matches!(aud, Value::Array(aud) if aud.is_empty()),
+        };
+
+        if !valid {
+            return Err(ErrorKind::InvalidAudience.into());
+        }
+    }
+
+    Ok(())
+}
+
+fn numeric(value: &Value) -> Option<u64> {
+    value
+        .as_u64()
+        .or_else(|| value.as_f64().filter(|v| *v >= 0.0).map(|v| v as u64))
+}
+
+fn strings(value: &Value) -> impl Iterator<Item = &str> {
+    let values = match value {
+        Value::Array(values) => values.as_slice(),
+        value => std::slice::from_ref(value),
+    };
+
+    values.iter().filter_map(Value::as_str)
+}
+
+#[cfg(test)]
+mod jwe_test {
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        jwt::{JweKey, JwtContext, JwtErrorKind},
+        utils::utc_now_secs,
+    };
+
+    const KEY: &[u8; 32] = b"0123456789abcdef0123456789abcdef";
+    const OTHER_KEY: &[u8; 32] = b"fedcba9876543210fedcba9876543210";
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    struct AT {
+        email: String,
+        exp: u64,
+    }
+
+    fn token() -> AT {
+        AT {
+            email: "admin@example.com".into(),
+            exp: utc_now_secs() + 1000,
+        }
+    }
+
+    #[test]
+    fn dir() {
+        let context = JwtContext::builder()
+            .encryption_key(JweKey::dir(KEY).unwrap())
+            .build::<AT>();
+
+        let jwe = context.encode_token(&token()).unwrap();
+        assert!(jwe.split('.').count() == 5);
+        assert!(!jwe.contains("admin"));
+
+        let decoded = context.decode(&jwe).unwrap();
+        assert!(decoded.claims.email == "admin@example.com");
+        assert!(decoded.header.typ.as_deref() == Some("JWT"));
+    }
+
+    #[test]
+    fn a256kw() {
+        let context = JwtContext::builder()
+            .encryption_key(JweKey::a256kw(KEY).unwrap().kid("key-1"))
+            .build::<AT>();
+
+        let jwe = context.encode_token(&token()).unwrap();
+        let decoded = context.decode(&jwe).unwrap();
+
+        assert!(decoded.claims.email == "admin@example.com");
+        assert!(decoded.header.kid.as_deref() == Some("key-1"));
+
+        // A random content encryption key is used for every token.
+        let other = context.encode_token(&token()).unwrap();
+        assert!(jwe.split('.').nth(1) != other.split('.').nth(1));
+    }
+
+    #[test]
+    fn wrong_key() {
+        let context = JwtContext::builder()
+            .encryption_key(JweKey::a256kw(KEY).unwrap())
+            .build::<AT>();
+
+        let other_context = JwtContext::builder()
+            .encryption_key(JweKey::a256kw(OTHER_KEY).unwrap())
+            .build::<AT>();
+
+        let dir_context = JwtContext::builder()
+            .encryption_key(JweKey::dir(KEY).unwrap())
+            .build::<AT>();
+
+        let jwe = context.encode_token(&token()).unwrap();
+
+        let err = other_context.decode(&jwe).unwrap_err();
+        assert!(matches!(err.kind(), JwtErrorKind::InvalidSignature));
+
+        let err = dir_context.decode(&jwe).unwrap_err();
+        assert!(matches!(err.kind(), JwtErrorKind::InvalidAlgorithm));
+    }
+
+    #[test]
+    fn tampered() {
+        let context = JwtContext::builder()
+            .encryption_key(JweKey::dir(KEY).unwrap())
+            .build::<AT>();
+
+        let jwe = context.encode_token(&token()).unwrap();
+        let mut segments: Vec<String> = jwe.split('.').map(str::to_string).collect();
+
+        // Flip the first character of the ciphertext.
+        let first = if segments[3].starts_with('A') {
+            "B"
+        } else {
+            "A"
+        };
+        segments[3].replace_range(..1, first);
+
+        let err = context.decode(segments.join(".")).unwrap_err();
+        assert!(matches!(err.kind(), JwtErrorKind::InvalidSignature));
+    }
+
+    #[test]
+    fn expired() {
+        let context = JwtContext::builder()
+            .encryption_key(JweKey::dir(KEY).unwrap())
+            .build::<AT>();
+
+        let jwe = context
+            .encode_token(&AT {
+                email: "admin@example.com".into(),
+                exp: utc_now_secs() - 1000,
+            })
+            .unwrap();
+
+        let err = context.decode(&jwe).unwrap_err();
+        assert!(matches!(err.kind(), JwtErrorKind::ExpiredSignature));
+    }
+
+    #[test]
+    fn invalid_key_length() {
+        assert!(JweKey::dir(b"too-short").is_err());
+        assert!(JweKey::a256kw([0u8; 33]).is_err());
+    }
+}
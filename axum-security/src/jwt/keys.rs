@@ -0,0 +1,134 @@
+use std::{fs, path::Path, str::FromStr};
+
+use jsonwebtoken::{
+    Algorithm, DecodingKey, EncodingKey,
+    errors::ErrorKind,
+    jwk::{AlgorithmParameters, EllipticCurve, Jwk},
+};
+
+use crate::{
+    jwt::{JwtBuilderError, JwtError},
+    utils::get_env,
+};
+
+#[derive(Clone, Copy)]
+pub(crate) enum KeyFamily {
+    Rsa,
+    Ec,
+    Ed25519,
+}
+
+impl KeyFamily {
+    pub(crate) fn algorithm(self) -> Algorithm {
+        match self {
+            KeyFamily::Rsa => Algorithm::RS256,
+            KeyFamily::Ec => Algorithm::ES256,
+            KeyFamily::Ed25519 => Algorithm::EdDSA,
+        }
+    }
+
+    pub(crate) fn encoding_key(self, source: KeySource) -> Result<EncodingKey, JwtBuilderError> {
+        let key = source.data?;
+
+        let key = match (self, source.format) {
+            (KeyFamily::Rsa, KeyFormat::Pem) => EncodingKey::from_rsa_pem(&key)?,
+            (KeyFamily::Ec, KeyFormat::Pem) => EncodingKey::from_ec_pem(&key)?,
+            (KeyFamily::Ed25519, KeyFormat::Pem) => EncodingKey::from_ed_pem(&key)?,
+            (KeyFamily::Rsa, KeyFormat::Der) => EncodingKey::from_rsa_der(&key),
+            (KeyFamily::Ec, KeyFormat::Der) => EncodingKey::from_ec_der(&key),
+            (KeyFamily::Ed25519, KeyFormat::Der) => EncodingKey::from_ed_der(&key),
+        };
+
+        Ok(key)
+    }
+
+    pub(crate) fn decoding_key(self, source: KeySource) -> Result<DecodingKey, JwtBuilderError> {
+        let key = source.data?;
+
+        let key = match (self, source.format) {
+            (KeyFamily::Rsa, KeyFormat::Pem) => DecodingKey::from_rsa_pem(&key)?,
+            (KeyFamily::Ec, KeyFormat::Pem) => DecodingKey::from_ec_pem(&key)?,
+            (KeyFamily::Ed25519, KeyFormat::Pem) => DecodingKey::from_ed_pem(&key)?,
+            (KeyFamily::Rsa, KeyFormat::Der) => DecodingKey::from_rsa_der(&key),
+            (KeyFamily::Ec, KeyFormat::Der) => DecodingKey::from_ec_der(&key),
+            (KeyFamily::Ed25519, KeyFormat::Der) => DecodingKey::from_ed_der(&key),
+        };
+
+        Ok(key)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum KeyFormat {
+    Pem,
+    Der,
+}
+
+/// The raw bytes of a key, or the error that occurred while reading them.
+pub(crate) struct KeySource {
+    format: KeyFormat,
+    data: Result<Vec<u8>, JwtBuilderError>,
+}
+
+impl KeySource {
+    pub(crate) fn pem(key: impl AsRef<[u8]>) -> Self {
+        KeySource {
+            format: KeyFormat::Pem,
+            data: Ok(key.as_ref().to_vec()),
+        }
+    }
+
+    pub(crate) fn der(key: impl AsRef<[u8]>) -> Self {
+        KeySource {
+            format: KeyFormat::Der,
+            data: Ok(key.as_ref().to_vec()),
+        }
+    }
+
+    pub(crate) fn pem_file(path: impl AsRef<Path>) -> Self {
+        KeySource {
+            format: KeyFormat::Pem,
+            data: read_file(path.as_ref()),
+        }
+    }
+
+    pub(crate) fn pem_env(name: &str) -> Self {
+        Self::pem(get_env(name))
+    }
+}
+
+pub(crate) fn read_file(path: &Path) -> Result<Vec<u8>, JwtBuilderError> {
+    fs::read(path).map_err(|e| JwtBuilderError::ReadKey(path.to_path_buf(), e))
+}
+
+/// Parses a JWK from json and returns its decoding key and algorithm.
+pub(crate) fn jwk_from_json(json: &[u8]) -> Result<(DecodingKey, Algorithm), JwtBuilderError> {
+    let jwk = serde_json::from_slice::<Jwk>(json)
+        .map_err(|e| JwtBuilderError::InvalidKey(ErrorKind::Json(std::sync::Arc::new(e)).into()))?;
+
+    jwk_keys(&jwk)
+}
+
+pub(crate) fn jwk_keys(jwk: &Jwk) -> Result<(DecodingKey, Algorithm), JwtBuilderError> {
+    Ok((DecodingKey::from_jwk(jwk)?, jwk_algorithm(jwk)?))
+}
+
+/// Uses the `alg` of the JWK when it is set, otherwise the algorithm is derived from the key type.
+fn jwk_algorithm(jwk: &Jwk) -> Result<Algorithm, JwtError> {
+    if let Some(alg) = jwk.common.key_algorithm {
+        return Algorithm::from_str(&alg.to_string());
+    }
+
+    match &jwk.algorithm {
+        AlgorithmParameters::RSA(_) => Ok(Algorithm::RS256),
+        AlgorithmParameters::EllipticCurve(params) => match params.curve {
+            EllipticCurve::P256 => Ok(Algorithm::ES256),
+            EllipticCurve::P384 => Ok(Algorithm::ES384),
+            _ => Err(ErrorKind::InvalidAlgorithm.into()),
+        },
+        AlgorithmParameters::OctetKeyPair(params) if params.curve == EllipticCurve::Ed25519 => {
+            Ok(Algorithm::EdDSA)
+        }
+        _ => Err(ErrorKind::InvalidAlgorithm.into()),
+    }
+}
@@ -1,35 +1,71 @@
 mod builder;
+mod claims;
+mod codec;
+#[cfg(feature = "dpop")]
+mod dpop;
+#[cfg(feature = "jwe")]
+mod jwe;
+mod keys;
 mod service;
 mod session;
+mod verifier;
 
-use std::{borrow::Cow, convert::Infallible, marker::PhantomData, sync::Arc};
+use std::{borrow::Cow, collections::HashMap, convert::Infallible, marker::PhantomData, sync::Arc};
 
-use axum::extract::{FromRef, FromRequestParts};
+use axum::extract::{FromRef, FromRequestParts, Query};
 pub use builder::{JwtBuilderError, JwtContextBuilder};
+pub use claims::{Audience, Claims, Confirmation};
 #[cfg(feature = "cookie")]
 use cookie_monster::{Cookie, CookieBuilder};
-use http::{HeaderMap, HeaderName, request::Parts};
-use jsonwebtoken::{TokenData, decode, encode};
+#[cfg(feature = "dpop")]
+pub use dpop::DpopProof;
+use http::{HeaderMap, HeaderName, HeaderValue, request::Parts};
+use jsonwebtoken::TokenData;
+#[cfg(feature = "jwe")]
+pub use jwe::JweKey;
 use serde::{Serialize, de::DeserializeOwned};
 pub use session::Jwt;
+pub use verifier::JwtVerifier;
+
+#[cfg(any(feature = "cookie", feature = "dpop"))]
+use serde_json::{Map, Value};
+#[cfg(feature = "cookie")]
+use std::{error::Error, fmt::Display, time::Duration};
+#[cfg(feature = "cookie")]
+use uuid::Uuid;
+
+#[cfg(feature = "cookie")]
+use crate::utils::utc_now_secs;
+use crate::{
+    jwt::{claims::RegisteredClaims, codec::Codec},
+    utils::enforce::Enforcement,
+};
 
 pub use jsonwebtoken::{
-    DecodingKey, EncodingKey, Header, Validation,
+    Algorithm, DecodingKey, EncodingKey, Header, Validation,
     errors::{Error as JwtError, ErrorKind as JwtErrorKind},
     get_current_timestamp,
+    jwk::Jwk,
 };
 
 pub struct JwtContext<T>(Arc<JwtContextInner<T>>);
 
 struct JwtContextInner<T> {
-    encoding_key: EncodingKey,
-    decoding_key: DecodingKey,
-    jwt_header: Header,
+    codec: Codec,
     validation: Validation,
     data: PhantomData<T>,
-    extract: ExtractFrom,
+    extract: Vec<ExtractFrom>,
+    reject_conflicting_tokens: bool,
+    enforcement: Enforcement,
+    #[cfg(feature = "cookie")]
+    renew_cookie_within: Option<Duration>,
+    #[cfg(feature = "dpop")]
+    dpop: Option<dpop::Dpop>,
+    registered: RegisteredClaims,
 }
 
+pub(crate) type ExtractFn = Arc<dyn Fn(&Parts) -> Option<String> + Send + Sync>;
+
 pub(crate) enum ExtractFrom {
     #[cfg(feature = "cookie")]
     Cookie(Box<CookieBuilder>),
@@ -37,68 +73,323 @@ pub(crate) enum ExtractFrom {
         header: HeaderName,
         prefix: Cow<'static, str>,
     },
+    Query(Cow<'static, str>),
+    Custom(ExtractFn),
 }
+
+impl ExtractFrom {
+    fn token(&self, parts: &Parts) -> Option<String> {
+        match self {
+            #[cfg(feature = "cookie")]
+            ExtractFrom::Cookie(cookie) => {
+                let jar = cookie_monster::CookieJar::from_headers(&parts.headers);
+                let cookie = jar.get(cookie.get_name())?;
+
+                Some(cookie.value().to_string())
+            }
+            ExtractFrom::Header { header, prefix } => {
+                let header = parts.headers.get(header)?.to_str().ok()?;
+
+                jwt_from_header_value(header, prefix).map(str::to_string)
+            }
+            ExtractFrom::Query(name) => {
+                let Query(mut params) =
+                    Query::<HashMap<String, String>>::try_from_uri(&parts.uri).ok()?;
+
+                params.remove(name.as_ref())
+            }
+            ExtractFrom::Custom(f) => f(parts),
+        }
+    }
+}
+
+pub(crate) struct ExtractedToken {
+    pub(crate) token: String,
+    pub(crate) from_cookie: bool,
+}
+
+/// Returned when a request carries more than one distinct token and
+/// [`JwtContextBuilder::reject_conflicting_tokens`] is enabled.
+#[derive(Debug)]
+pub(crate) struct ConflictingTokens;
+
 impl JwtContext<()> {
     pub fn builder() -> JwtContextBuilder {
         JwtContextBuilder::new()
     }
 }
 
+impl<T> JwtContext<T> {
+    pub(crate) fn rejects_missing_token(&self, parts: &Parts) -> bool {
+        self.0.enforcement.rejects(&parts.method, &parts.uri)
+    }
+
+    /// The `WWW-Authenticate` header for `401 Unauthorized` responses, if one is needed.
+    pub(crate) fn challenge(&self, error: Option<&str>) -> Option<HeaderValue> {
+        #[cfg(feature = "dpop")]
+        if let Some(dpop) = &self.0.dpop {
+            return Some(dpop.challenge(error));
+        }
+
+        let _ = error;
+        None
+    }
+
+    #[cfg(feature = "cookie")]
+    fn cookie_builder(&self) -> Option<&CookieBuilder> {
+        self.0.extract.iter().find_map(|e| match e {
+            ExtractFrom::Cookie(cookie_builder) => Some(cookie_builder.as_ref()),
+            _ => None,
+        })
+    }
+
+    /// Looks for a token in the configured sources, in order. The first token that is present
+    /// is returned.
+    pub(crate) fn token_from_parts(
+        &self,
+        parts: &Parts,
+    ) -> Result<Option<ExtractedToken>, ConflictingTokens> {
+        let mut tokens = self
+            .0
+            .extract
+            .iter()
+            .filter_map(|e| e.token(parts).map(|token| (token, e)));
+
+        let Some((token, source)) = tokens.next() else {
+            return Ok(None);
+        };
+
+        if self.0.reject_conflicting_tokens && tokens.any(|(other, _)| other != token) {
+            tracing::debug!("request contains conflicting tokens");
+            return Err(ConflictingTokens);
+        }
+
+        #[cfg(feature = "cookie")]
+        let from_cookie = matches!(source, ExtractFrom::Cookie(_));
+        #[cfg(not(feature = "cookie"))]
+        let from_cookie = {
+            let _ = source;
+            false
+        };
+
+        Ok(Some(ExtractedToken { token, from_cookie }))
+    }
+
+    /// Returns a `Set-Cookie` header with a renewed token, if cookie renewal is enabled and the
+    /// token was read from a cookie that expires within the renewal window.
+    pub(crate) fn renewed_cookie_header(&self, extracted: &ExtractedToken) -> Option<HeaderValue> {
+        if !extracted.from_cookie {
+            return None;
+        }
+
+        #[cfg(feature = "cookie")]
+        {
+            let cookie = self.renew_cookie(&extracted.token)?;
+            cookie.serialize_encoded().ok()?.parse().ok()
+        }
+        #[cfg(not(feature = "cookie"))]
+        None
+    }
+
+    /// Returns true if `headers` already set the jwt cookie, e.g. because the handler logged
+    /// the user out. A renewed cookie must not override it.
+    pub(crate) fn sets_jwt_cookie(&self, headers: &HeaderMap) -> bool {
+        #[cfg(feature = "cookie")]
+        if let Some(cookie_builder) = self.cookie_builder() {
+            let name = cookie_builder.get_name();
+
+            return headers
+                .get_all(http::header::SET_COOKIE)
+                .iter()
+                .any(|value| {
+                    value
+                        .to_str()
+                        .ok()
+                        .and_then(|value| value.split_once('='))
+                        .is_some_and(|(cookie_name, _)| cookie_name.trim() == name)
+                });
+        }
+
+        #[cfg(not(feature = "cookie"))]
+        let _ = headers;
+        false
+    }
+
+    #[cfg(feature = "cookie")]
+    fn renew_cookie(&self, token: &str) -> Option<Cookie> {
+        let window = self.0.renew_cookie_within?;
+        let cookie_builder = self.cookie_builder()?;
+
+        // Decode into a map so every claim of `T` is kept as is, only the time based claims are
+        // changed.
+        let mut claims = self
+            .0
+            .codec
+            .decode::<Map<String, Value>>(token.as_bytes(), &self.0.validation)
+            .ok()?
+            .claims;
+
+        let now = utc_now_secs();
+        let exp = claims.get("exp")?.as_u64()?;
+
+        if exp > now + window.as_secs() {
+            return None;
+        }
+
+        let ttl = match self.0.registered.ttl {
+            Some(ttl) => ttl.as_secs(),
+            None => exp.checked_sub(claims.get("iat")?.as_u64()?)?,
+        };
+
+        tracing::debug!("renewing jwt cookie");
+        claims.insert("exp".into(), (now + ttl).into());
+
+        if claims.contains_key("iat") {
+            claims.insert("iat".into(), now.into());
+        }
+
+        if claims.contains_key("jti") {
+            claims.insert("jti".into(), Uuid::now_v7().as_simple().to_string().into());
+        }
+
+        let token = self.0.codec.encode(&claims).ok()?;
+        Some(cookie_builder.clone().value(token).build())
+    }
+}
+
 impl<T: Serialize> JwtContext<T> {
     pub fn encode_token(&self, data: &T) -> jsonwebtoken::errors::Result<String> {
-        encode(&self.0.jwt_header, data, &self.0.encoding_key)
+        self.0.codec.encode(data)
     }
 
     #[cfg(feature = "cookie")]
-    pub fn encode_token_to_cookie(&self, data: &T) -> jsonwebtoken::errors::Result<Cookie> {
-        let token = encode(&self.0.jwt_header, data, &self.0.encoding_key)?;
-        match &self.0.extract {
-            ExtractFrom::Cookie(cookie_builder) => Ok(cookie_builder.clone().value(token).build()),
-            ExtractFrom::Header { .. } => panic!("no cookie config set"),
-        }
+    pub fn encode_token_to_cookie(&self, data: &T) -> Result<Cookie, JwtCookieError> {
+        let cookie_builder = self
+            .cookie_builder()
+            .ok_or(JwtCookieError::CookieNotConfigured)?;
+
+        let token = self.0.codec.encode(data)?;
+        Ok(cookie_builder.clone().value(token).build())
     }
 
     #[cfg(feature = "cookie")]
-    pub fn logout_cookie(&self) -> Cookie {
-        match &self.0.extract {
-            ExtractFrom::Cookie(cookie_builder) => {
-                use cookie_monster::Expires;
-
-                cookie_builder
-                    .clone()
-                    .expires(Expires::remove())
-                    .max_age_secs(0)
-                    .value("")
-                    .build()
-            }
-            ExtractFrom::Header { .. } => panic!("no cookie config set"),
+    pub fn logout_cookie(&self) -> Result<Cookie, JwtCookieError> {
+        use cookie_monster::Expires;
+
+        let cookie_builder = self
+            .cookie_builder()
+            .ok_or(JwtCookieError::CookieNotConfigured)?;
+
+        Ok(cookie_builder
+            .clone()
+            .expires(Expires::remove())
+            .max_age_secs(0)
+            .value("")
+            .build())
+    }
+}
+
+#[cfg(feature = "cookie")]
+#[derive(Debug)]
+pub enum JwtCookieError {
+    /// The context was built without a cookie source, see
+    /// [`JwtContextBuilder::extract_cookie`].
+    CookieNotConfigured,
+    Jwt(JwtError),
+}
+
+#[cfg(feature = "cookie")]
+impl From<JwtError> for JwtCookieError {
+    fn from(value: JwtError) -> Self {
+        JwtCookieError::Jwt(value)
+    }
+}
+
+#[cfg(feature = "cookie")]
+impl Display for JwtCookieError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JwtCookieError::CookieNotConfigured => f.write_str("no cookie config set"),
+            JwtCookieError::Jwt(error) => Display::fmt(error, f),
         }
     }
 }
 
-impl<T: DeserializeOwned> JwtContext<T> {
-    pub fn decode(&self, jwt: impl AsRef<[u8]>) -> Result<TokenData<T>, JwtError> {
-        decode(jwt.as_ref(), &self.0.decoding_key, &self.0.validation)
+#[cfg(feature = "cookie")]
+impl Error for JwtCookieError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            JwtCookieError::CookieNotConfigured => None,
+            JwtCookieError::Jwt(error) => Some(error),
+        }
     }
+}
 
-    pub(crate) fn decode_from_headers(&self, headers: &HeaderMap) -> Option<T> {
-        let result = match &self.0.extract {
-            #[cfg(feature = "cookie")]
-            ExtractFrom::Cookie(cookie) => {
-                let jar = cookie_monster::CookieJar::from_headers(headers);
-                let cookie = jar.get(cookie.get_name())?;
+impl<T: Serialize> JwtContext<Claims<T>> {
+    /// Wraps `data` in [`Claims`] and fills in the configured registered claims.
+    pub fn issue(&self, data: T) -> jsonwebtoken::errors::Result<String> {
+        self.issue_claims(Claims::new(data))
+    }
 
-                self.decode(cookie.value())
-            }
-            ExtractFrom::Header { header, prefix } => {
-                let authorization_header = headers.get(header)?.to_str().ok()?;
+    /// Fills in the registered claims that are not set yet and encodes the token.
+    ///
+    /// Fails with `MissingRequiredClaim` if the token has no `exp` while this context requires
+    /// one, so either configure a `ttl` or set `exp` on the claims.
+    pub fn issue_claims(&self, mut claims: Claims<T>) -> jsonwebtoken::errors::Result<String> {
+        claims.fill(&self.0.registered);
 
-                let jwt = jwt_from_header_value(authorization_header, prefix)?;
-                self.decode(jwt)
-            }
-        };
+        if claims.exp.is_none() && self.0.validation.required_spec_claims.contains("exp") {
+            return Err(JwtErrorKind::MissingRequiredClaim("exp".into()).into());
+        }
 
-        result.ok().map(|t| t.claims)
+        self.encode_token(&claims)
+    }
+}
+
+impl<T: DeserializeOwned> JwtContext<T> {
+    pub fn decode(&self, jwt: impl AsRef<[u8]>) -> Result<TokenData<T>, JwtError> {
+        self.0.codec.decode(jwt.as_ref(), &self.0.validation)
+    }
+
+    /// Decodes the token and adds it to the extensions of the request. When DPoP is enabled,
+    /// the proof of the request is checked too. The error is used for the `WWW-Authenticate`
+    /// challenge.
+    pub(crate) fn authenticate(
+        &self,
+        parts: &mut Parts,
+        token: &str,
+    ) -> Result<(), Option<&'static str>>
+    where
+        T: Send + Sync + Clone + 'static,
+    {
+        #[cfg(feature = "dpop")]
+        if let Some(dpop) = &self.0.dpop {
+            use dpop::DpopError;
+
+            let invalid_token = Some(DpopError::InvalidToken.code());
+
+            let claims = self
+                .0
+                .codec
+                .decode::<Map<String, Value>>(token.as_bytes(), &self.0.validation)
+                .map_err(|_| invalid_token)?
+                .claims;
+
+            let proof = dpop
+                .verify(parts, token, &claims)
+                .map_err(|e| Some(e.code()))?;
+
+            let claims =
+                serde_json::from_value::<T>(Value::Object(claims)).map_err(|_| invalid_token)?;
+
+            parts.extensions.insert(Jwt(claims));
+            parts.extensions.insert(proof);
+            return Ok(());
+        }
+
+        let token = self.decode(token).map_err(|_| None)?;
+        parts.extensions.insert(Jwt(token.claims));
+        Ok(())
     }
 }
 
@@ -201,4 +492,140 @@ mod jwt_test {
         assert!(resp.status() == StatusCode::UNAUTHORIZED);
         Ok(())
     }
+
+    #[cfg(feature = "cookie")]
+    #[tokio::test]
+    async fn renew_cookie() -> Result<(), Box<dyn Error>> {
+        use std::time::Duration;
+
+        use axum::{Router, body::Body, routing::get};
+        use http::{Request, header::SET_COOKIE};
+        use serde::Serialize;
+        use tower::ServiceExt;
+
+        use crate::{
+            jwt::{Jwt, JwtContext},
+            utils::utc_now_secs,
+        };
+
+        #[derive(Serialize, Deserialize, Clone)]
+        struct AT {
+            exp: u64,
+        }
+
+        let jwt_context = JwtContext::builder()
+            .extract_cookie("session")
+            .jwt_secret("test-secret")
+            .ttl(Duration::from_secs(1000))
+            .renew_cookie_within(Duration::from_secs(100))
+            .build::<AT>();
+
+        let router = Router::<()>::new()
+            .route("/", get(move |_: Jwt<AT>| async { StatusCode::OK }))
+            .layer(jwt_context.clone());
+
+        let almost_expired = jwt_context.encode_token_to_cookie(&AT {
+            exp: utc_now_secs() + 10,
+        })?;
+
+        let request = Request::get("/")
+            .header("cookie", format!("session={}", almost_expired.value()))
+            .body(Body::empty())?;
+
+        let resp = router.clone().oneshot(request).await.unwrap();
+        assert!(resp.status() == StatusCode::OK);
+
+        let set_cookie = resp.headers()[SET_COOKIE].to_str()?;
+        let token = set_cookie
+            .strip_prefix("session=")
+            .and_then(|c| c.split(';').next())
+            .unwrap();
+
+        let renewed = jwt_context.decode(token)?.claims;
+        assert!(renewed.exp > utc_now_secs() + 900);
+
+        let fresh = jwt_context.encode_token_to_cookie(&AT {
+            exp: utc_now_secs() + 1000,
+        })?;
+
+        let request = Request::get("/")
+            .header("cookie", format!("session={}", fresh.value()))
+            .body(Body::empty())?;
+
+        let resp = router.clone().oneshot(request).await.unwrap();
+        assert!(resp.status() == StatusCode::OK);
+        assert!(resp.headers().get(SET_COOKIE).is_none());
+        Ok(())
+    }
+
+    #[cfg(feature = "cookie")]
+    #[tokio::test]
+    async fn logout_within_renewal_window() -> Result<(), Box<dyn Error>> {
+        use std::time::Duration;
+
+        use axum::{Router, body::Body, routing::get};
+        use http::{Request, header::SET_COOKIE};
+        use serde::Serialize;
+        use tower::ServiceExt;
+
+        use crate::{
+            jwt::{Jwt, JwtContext},
+            utils::utc_now_secs,
+        };
+
+        #[derive(Serialize, Deserialize, Clone)]
+        struct AT {
+            exp: u64,
+        }
+
+        let jwt_context = JwtContext::builder()
+            .extract_cookie("session")
+            .jwt_secret("test-secret")
+            .ttl(Duration::from_secs(1000))
+            .renew_cookie_within(Duration::from_secs(100))
+            .build::<AT>();
+
+        let logout_context = jwt_context.clone();
+        let router = Router::<()>::new()
+            .route(
+                "/logout",
+                get(move |_: Jwt<AT>| async move {
+                    let cookie = logout_context.logout_cookie().unwrap();
+                    [(SET_COOKIE, cookie.serialize().unwrap())]
+                }),
+            )
+            .layer(jwt_context.clone());
+
+        let almost_expired = jwt_context.encode_token_to_cookie(&AT {
+            exp: utc_now_secs() + 10,
+        })?;
+
+        let request = Request::get("/logout")
+            .header("cookie", format!("session={}", almost_expired.value()))
+            .body(Body::empty())?;
+
+        let resp = router.oneshot(request).await.unwrap();
+        assert!(resp.status() == StatusCode::OK);
+
+        let set_cookies: Vec<_> = resp.headers().get_all(SET_COOKIE).iter().collect();
+        assert_eq!(set_cookies.len(), 1);
+        assert!(set_cookies[0].to_str()?.starts_with("session=;"));
+        Ok(())
+    }
+
+    #[cfg(feature = "cookie")]
+    #[test]
+    fn cookie_not_configured() {
+        use crate::jwt::{JwtContext, JwtCookieError};
+
+        let jwt_context = JwtContext::builder()
+            .jwt_secret("test-secret")
+            .build::<u64>();
+
+        let err = jwt_context.encode_token_to_cookie(&1).unwrap_err();
+        assert!(matches!(err, JwtCookieError::CookieNotConfigured));
+
+        let err = jwt_context.logout_cookie().unwrap_err();
+        assert!(matches!(err, JwtCookieError::CookieNotConfigured));
+    }
 }
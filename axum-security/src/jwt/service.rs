@@ -1,10 +1,21 @@
-use std::task::{Context, Poll};
+use std::{
+    convert::Infallible,
+    pin::Pin,
+    task::{Context, Poll},
+};
 
-use axum::extract::Request;
+use axum::{
+    extract::Request,
+    http::{
+        StatusCode,
+        header::{SET_COOKIE, WWW_AUTHENTICATE},
+    },
+    response::{IntoResponse, Response},
+};
 use serde::de::DeserializeOwned;
 use tower::{Layer, Service};
 
-use crate::jwt::{Jwt, JwtContext};
+use crate::jwt::JwtContext;
 
 pub struct JwtService<T, SERV> {
     inner: JwtContext<T>,
@@ -13,24 +24,71 @@ pub struct JwtService<T, SERV> {
 
 impl<T, SERV> Service<Request> for JwtService<T, SERV>
 where
-    SERV: Service<Request>,
+    SERV: Service<Request, Error = Infallible>,
+    SERV::Response: IntoResponse,
+    SERV::Future: Send + 'static,
     T: DeserializeOwned + Send + Sync + 'static + Clone,
 {
-    type Response = <SERV>::Response;
+    type Response = Response;
 
-    type Error = <SERV>::Error;
+    type Error = Infallible;
 
-    type Future = <SERV>::Future;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         self.rest.poll_ready(cx)
     }
 
-    fn call(&mut self, mut req: Request) -> Self::Future {
-        if let Some(user) = self.inner.decode_from_headers(req.headers()) {
-            req.extensions_mut().insert(Jwt(user));
+    fn call(&mut self, req: Request) -> Self::Future {
+        let (mut parts, body) = req.into_parts();
+        let mut set_cookie = None;
+        let mut authenticated = false;
+        let mut error = None;
+
+        match self.inner.token_from_parts(&parts) {
+            Ok(Some(extracted)) => match self.inner.authenticate(&mut parts, &extracted.token) {
+                Ok(()) => {
+                    set_cookie = self.inner.renewed_cookie_header(&extracted);
+                    authenticated = true;
+                }
+                Err(e) => error = e,
+            },
+            Ok(None) => {}
+            Err(_) => return Box::pin(async { Ok(StatusCode::BAD_REQUEST.into_response()) }),
+        }
+
+        let challenge = self.inner.challenge(error);
+
+        if !authenticated && self.inner.rejects_missing_token(&parts) {
+            let mut res = StatusCode::UNAUTHORIZED.into_response();
+            if let Some(challenge) = challenge {
+                res.headers_mut().insert(WWW_AUTHENTICATE, challenge);
+            }
+
+            return Box::pin(async { Ok(res) });
         }
-        self.rest.call(req)
+
+        let future = self.rest.call(Request::from_parts(parts, body));
+        let context = self.inner.clone();
+
+        Box::pin(async move {
+            let mut res = future.await.into_response();
+
+            if let Some(set_cookie) = set_cookie
+                && !context.sets_jwt_cookie(res.headers())
+            {
+                res.headers_mut().append(SET_COOKIE, set_cookie);
+            }
+
+            if let Some(challenge) = challenge
+                && res.status() == StatusCode::UNAUTHORIZED
+                && !res.headers().contains_key(WWW_AUTHENTICATE)
+            {
+                res.headers_mut().insert(WWW_AUTHENTICATE, challenge);
+            }
+
+            Ok(res)
+        })
     }
 }
 
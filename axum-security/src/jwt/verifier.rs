@@ -0,0 +1,48 @@
+use std::convert::Infallible;
+
+use axum::extract::{FromRef, FromRequestParts};
+use http::request::Parts;
+use jsonwebtoken::TokenData;
+use serde::de::DeserializeOwned;
+use tower::Layer;
+
+use crate::jwt::{JwtContext, JwtError, service::JwtService};
+
+/// A [`JwtContext`] that only verifies tokens, for services that accept tokens issued
+/// elsewhere. Created with [`JwtContextBuilder::build_verifier`](crate::jwt::JwtContextBuilder::build_verifier).
+pub struct JwtVerifier<T>(pub(crate) JwtContext<T>);
+
+impl<T: DeserializeOwned> JwtVerifier<T> {
+    pub fn decode(&self, jwt: impl AsRef<[u8]>) -> Result<TokenData<T>, JwtError> {
+        self.0.decode(jwt)
+    }
+}
+
+impl<SERV, T> Layer<SERV> for JwtVerifier<T>
+where
+    T: 'static,
+{
+    type Service = JwtService<T, SERV>;
+
+    fn layer(&self, inner: SERV) -> Self::Service {
+        self.0.layer(inner)
+    }
+}
+
+impl<S, U> FromRequestParts<S> for JwtVerifier<U>
+where
+    JwtVerifier<U>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(_parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_ref(state))
+    }
+}
+
+impl<T> Clone for JwtVerifier<T> {
+    fn clone(&self) -> Self {
+        JwtVerifier(self.0.clone())
+    }
+}
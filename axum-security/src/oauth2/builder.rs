@@ -1,15 +1,31 @@
 use std::{borrow::Cow, error::Error, fmt::Display, sync::Arc, time::Duration};
 
 use cookie_monster::CookieBuilder;
+#[cfg(feature = "oidc")]
+use jsonwebtoken::Algorithm;
+#[cfg(feature = "jwt")]
+use jsonwebtoken::{EncodingKey, Header};
 use oauth2::{
-    AuthUrl, Client, ClientId, ClientSecret, RedirectUrl, Scope, TokenUrl,
-    reqwest::Client as HttpClient, url,
+    AuthType, AuthUrl, Client, ClientId, ClientSecret, DeviceAuthorizationUrl, RedirectUrl,
+    RevocationUrl, Scope, TokenUrl,
+    reqwest::{Client as HttpClient, Identity, redirect::Policy},
+    url::{self, Url},
 };
 
+#[cfg(feature = "jwt")]
+use crate::oauth2::client_auth::AssertionKey;
+#[cfg(feature = "oidc")]
+use crate::oauth2::oidc::{Oidc, ProviderMetadata};
 use crate::{
+    cookie::CookieContext,
     http::default_reqwest_client,
     oauth2::{
-        OAuth2Context, OAuth2Handler, context::OAuth2ContextInner, cookie::OAuthCookieBuilder,
+        ClientCredentials, LoginStateStore, OAuth2Context, OAuth2Handler, ProviderTokens,
+        client_auth::ClientAuth,
+        context::OAuth2ContextInner,
+        cookie::OAuthCookieBuilder,
+        options::{AuthorizationParams, is_form_post},
+        state::ErasedLoginStateStore,
     },
     utils::get_env,
 };
@@ -25,6 +41,25 @@ pub struct OAuth2ContextBuilder {
     token_url: Option<String>,
     http_client: Option<HttpClient>,
     flow_type: FlowType,
+    auth_type: AuthType,
+    client_auth: ClientAuth,
+    tls_identity: Option<Identity>,
+    userinfo_url: Option<String>,
+    device_authorization_url: Option<String>,
+    revocation_url: Option<String>,
+    end_session_url: Option<String>,
+    pushed_authorization_url: Option<String>,
+    post_logout_redirect_url: Option<String>,
+    logout_path: Option<Cow<'static, str>>,
+    token_store: Option<CookieContext<ProviderTokens>>,
+    login_state_store: Option<ErasedLoginStateStore>,
+    authorization_params: AuthorizationParams,
+    #[cfg(feature = "oidc")]
+    issuer: Option<String>,
+    #[cfg(feature = "oidc")]
+    jwks_url: Option<String>,
+    #[cfg(feature = "oidc")]
+    id_token_algorithms: Vec<Algorithm>,
 }
 
 impl OAuth2ContextBuilder {
@@ -40,6 +75,25 @@ impl OAuth2ContextBuilder {
             token_url: None,
             http_client: None,
             flow_type: FlowType::AuthorizationCodeFlowPkce,
+            auth_type: AuthType::BasicAuth,
+            client_auth: ClientAuth::ClientSecret,
+            tls_identity: None,
+            userinfo_url: None,
+            device_authorization_url: None,
+            revocation_url: None,
+            end_session_url: None,
+            pushed_authorization_url: None,
+            post_logout_redirect_url: None,
+            logout_path: None,
+            token_store: None,
+            login_state_store: None,
+            authorization_params: Vec::new(),
+            #[cfg(feature = "oidc")]
+            issuer: None,
+            #[cfg(feature = "oidc")]
+            jwks_url: None,
+            #[cfg(feature = "oidc")]
+            id_token_algorithms: vec![Algorithm::RS256],
         }
     }
 
@@ -88,6 +142,69 @@ impl OAuth2ContextBuilder {
         self.token_url(get_env(name))
     }
 
+    /// Fetch the user profile from `userinfo_url` after login, see [`OAuth2Handler::Profile`].
+    pub fn userinfo_url(mut self, userinfo_url: impl Into<String>) -> Self {
+        self.userinfo_url = Some(userinfo_url.into());
+        self
+    }
+
+    pub fn userinfo_url_env(self, name: &str) -> Self {
+        self.userinfo_url(get_env(name))
+    }
+
+    /// Enables the device authorization grant, see
+    /// [`OAuth2Context::start_device_authorization`].
+    pub fn device_authorization_url(mut self, device_authorization_url: impl Into<String>) -> Self {
+        self.device_authorization_url = Some(device_authorization_url.into());
+        self
+    }
+
+    pub fn device_authorization_url_env(self, name: &str) -> Self {
+        self.device_authorization_url(get_env(name))
+    }
+
+    /// Revoke the provider tokens on logout (RFC 7009), see [`OAuth2Context::revoke`]. The url
+    /// must use https.
+    pub fn revocation_url(mut self, revocation_url: impl Into<String>) -> Self {
+        self.revocation_url = Some(revocation_url.into());
+        self
+    }
+
+    pub fn revocation_url_env(self, name: &str) -> Self {
+        self.revocation_url(get_env(name))
+    }
+
+    /// Redirect to the OpenID Connect `end_session_endpoint` of the provider on logout.
+    pub fn end_session_url(mut self, end_session_url: impl Into<String>) -> Self {
+        self.end_session_url = Some(end_session_url.into());
+        self
+    }
+
+    pub fn end_session_url_env(self, name: &str) -> Self {
+        self.end_session_url(get_env(name))
+    }
+
+    /// Where the user is sent after logout. It is passed to the end session url as
+    /// `post_logout_redirect_uri`, so it must be registered at the provider. Defaults to `/`.
+    pub fn post_logout_redirect_url(mut self, url: impl Into<String>) -> Self {
+        self.post_logout_redirect_url = Some(url.into());
+        self
+    }
+
+    /// Mounts a `POST` route at `path` that logs the user out, see [`OAuth2Context::logout`].
+    pub fn logout_path(mut self, path: impl Into<Cow<'static, str>>) -> Self {
+        self.logout_path = Some(path.into());
+        self
+    }
+
+    /// Keep the provider tokens of each login in `store`, they are available with the
+    /// [`AccessToken`](crate::oauth2::AccessToken) extractor. The store sets its own session
+    /// cookie, so give it a name that is not used by other sessions.
+    pub fn token_store(mut self, store: CookieContext<ProviderTokens>) -> Self {
+        self.token_store = Some(store);
+        self
+    }
+
     pub fn scopes(mut self, scopes: &[&str]) -> Self {
         self.scopes = scopes.iter().map(|s| Scope::new(s.to_string())).collect();
         self
@@ -127,6 +244,38 @@ impl OAuth2ContextBuilder {
         self
     }
 
+    /// Adds a param to every authorization request, for example `audience` or `prompt`. Params
+    /// that the login flow sets itself, like `state` and `redirect_uri`, can't be changed. Per
+    /// request params are set with [`ChallengeOptions::param`](crate::oauth2::ChallengeOptions::param).
+    pub fn authorization_param(
+        mut self,
+        name: impl Into<Cow<'static, str>>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.authorization_params.push((name.into(), value.into()));
+        self
+    }
+
+    /// Asks the provider to send the callback as a `POST` with the code in the form body
+    /// (`response_mode=form_post`). The callback route accepts `POST` requests too.
+    ///
+    /// The post comes from the site of the provider, so the login state cookie is made
+    /// `SameSite=None; Secure` to be sent with it. The `state` of the callback must still match
+    /// the cookie. Browsers treat `localhost` as secure, so this works without https in
+    /// development.
+    pub fn response_mode_form_post(self) -> Self {
+        self.authorization_param("response_mode", "form_post")
+    }
+
+    /// Keeps the CSRF token and PKCE verifier of login flows in `store` instead of a signed
+    /// cookie, for example a [`CookieContext<LoginState>`](CookieContext) with a server side store.
+    /// With [`response_mode_form_post`](Self::response_mode_form_post) its cookie must be
+    /// `SameSite=None` and `Secure`, or building the context fails.
+    pub fn login_state_store(mut self, store: impl LoginStateStore) -> Self {
+        self.login_state_store = Some(Arc::new(store));
+        self
+    }
+
     /// max length of the entire login flow.
     pub fn max_login_duration(mut self, duration: Duration) -> Self {
         self.cookie_builder
@@ -150,6 +299,123 @@ impl OAuth2ContextBuilder {
         self
     }
 
+    /// Sends the authorization request to the pushed authorization request endpoint (RFC 9126)
+    /// first, the user is redirected with the returned `request_uri`.
+    pub fn pushed_authorization_requests(mut self, par_url: impl Into<String>) -> Self {
+        self.pushed_authorization_url = Some(par_url.into());
+        self
+    }
+
+    pub fn pushed_authorization_requests_env(self, name: &str) -> Self {
+        self.pushed_authorization_requests(get_env(name))
+    }
+
+    /// How the client id and secret are sent to the token endpoint.
+    pub(crate) fn auth_type(mut self, auth_type: AuthType) -> Self {
+        self.auth_type = auth_type;
+        self
+    }
+
+    /// Authenticate to the token endpoint with a jwt signed with `key` (RFC 7523) instead of the
+    /// client secret. `header` sets the algorithm and the `kid` of the key.
+    #[cfg(feature = "jwt")]
+    pub fn private_key_jwt(mut self, key: EncodingKey, header: Header) -> Self {
+        self.client_auth = ClientAuth::PrivateKeyJwt(Box::new(AssertionKey { key, header }));
+        self
+    }
+
+    /// Authenticate to the token endpoint with a client certificate (RFC 8705) instead of the
+    /// client secret. The certificate is added to the default http client, so this can't be
+    /// combined with a custom [`http_client`](Self::http_client).
+    pub fn tls_client_auth(mut self, identity: Identity) -> Self {
+        self.client_auth = ClientAuth::Tls;
+        self.tls_identity = Some(identity);
+        self
+    }
+
+    /// The http client, the auth type and the client secret for the configured client
+    /// authentication.
+    fn client_auth_parts(
+        &self,
+    ) -> Result<(HttpClient, AuthType, Option<String>), OAuth2BuilderError> {
+        let http_client = match (self.http_client.clone(), self.tls_identity.clone()) {
+            (Some(_), Some(_)) => return Err(OAuth2BuilderError::TlsIdentityWithHttpClient),
+            (Some(http_client), None) => http_client,
+            (None, Some(identity)) => HttpClient::builder()
+                .redirect(Policy::none())
+                .identity(identity)
+                .build()
+                .map_err(|e| OAuth2BuilderError::HttpClient(e.to_string()))?,
+            (None, None) => default_reqwest_client(),
+        };
+
+        match self.client_auth {
+            ClientAuth::ClientSecret => Ok((
+                http_client,
+                self.auth_type.clone(),
+                self.client_secret.clone(),
+            )),
+            // The client is identified by the client id in the request body.
+            _ => Ok((http_client, AuthType::RequestBody, None)),
+        }
+    }
+
+    /// Log in with OpenID Connect. ID tokens must be issued by `issuer` and are verified with the
+    /// keys from [`jwks_url`](Self::jwks_url). The `openid` scope is added automatically.
+    #[cfg(feature = "oidc")]
+    pub fn openid_connect(mut self, issuer: impl Into<String>) -> Self {
+        self.issuer = Some(issuer.into());
+        self
+    }
+
+    #[cfg(feature = "oidc")]
+    pub fn jwks_url(mut self, jwks_url: impl Into<String>) -> Self {
+        self.jwks_url = Some(jwks_url.into());
+        self
+    }
+
+    /// The algorithms that ID tokens can be signed with, defaults to `RS256`.
+    #[cfg(feature = "oidc")]
+    pub fn id_token_algorithms(mut self, algorithms: &[Algorithm]) -> Self {
+        self.id_token_algorithms = algorithms.to_vec();
+        self
+    }
+
+    /// Configures OpenID Connect from the `.well-known/openid-configuration` document of
+    /// `issuer`. This sets the authorization, token and jwks urls.
+    #[cfg(feature = "oidc")]
+    pub async fn discover(self, issuer: impl Into<String>) -> Result<Self, OAuth2BuilderError> {
+        let issuer = issuer.into();
+        let http_client = self
+            .http_client
+            .clone()
+            .unwrap_or_else(default_reqwest_client);
+
+        let metadata = ProviderMetadata::discover(&http_client, &issuer)
+            .await
+            .map_err(OAuth2BuilderError::Discovery)?;
+
+        let mut builder = self;
+        if let Some(revocation_endpoint) = &metadata.revocation_endpoint {
+            builder = builder.revocation_url(revocation_endpoint.clone());
+        }
+        if let Some(end_session_endpoint) = &metadata.end_session_endpoint {
+            builder = builder.end_session_url(end_session_endpoint.clone());
+        }
+        if metadata.require_pushed_authorization_requests
+            && let Some(par_endpoint) = &metadata.pushed_authorization_request_endpoint
+        {
+            builder = builder.pushed_authorization_requests(par_endpoint.clone());
+        }
+
+        Ok(builder
+            .auth_url(metadata.authorization_endpoint.clone())
+            .token_url(metadata.token_endpoint.clone())
+            .jwks_url(metadata.jwks_uri.clone())
+            .id_token_algorithms(&metadata.algorithms())
+            .openid_connect(issuer))
+    }
+
     pub fn build<T>(self, inner: T) -> OAuth2Context<T>
     where
         T: OAuth2Handler,
@@ -161,6 +427,15 @@ impl OAuth2ContextBuilder {
     where
         T: OAuth2Handler,
     {
+        let (http_client, auth_type, client_secret) = self.client_auth_parts()?;
+
+        if let Some(store) = &self.login_state_store
+            && is_form_post(&self.authorization_params)
+            && !store.supports_form_post()
+        {
+            return Err(OAuth2BuilderError::LoginStateStoreWithoutFormPost);
+        }
+
         let client_id = self
             .client_id
             .ok_or(OAuth2BuilderError::MissingClientId)
@@ -181,27 +456,127 @@ impl OAuth2ContextBuilder {
 
         let token_url = TokenUrl::new(token_url).map_err(OAuth2BuilderError::InvalidTokenUrl)?;
 
+        let device_authorization_url = self
+            .device_authorization_url
+            .map(DeviceAuthorizationUrl::new)
+            .transpose()
+            .map_err(OAuth2BuilderError::InvalidDeviceAuthorizationUrl)?;
+
+        let revocation_url = self
+            .revocation_url
+            .map(RevocationUrl::new)
+            .transpose()
+            .map_err(OAuth2BuilderError::InvalidRevocationUrl)?;
+
+        let end_session_url = self
+            .end_session_url
+            .as_deref()
+            .map(Url::parse)
+            .transpose()
+            .map_err(OAuth2BuilderError::InvalidEndSessionUrl)?;
+
+        let par_url = self
+            .pushed_authorization_url
+            .as_deref()
+            .map(Url::parse)
+            .transpose()
+            .map_err(OAuth2BuilderError::InvalidPushedAuthorizationUrl)?;
+
         let mut basic_client = Client::new(client_id)
             .set_redirect_uri(redirect_url)
             .set_auth_uri(auth_url)
-            .set_token_uri(token_url);
+            .set_token_uri(token_url)
+            .set_device_authorization_url_option(device_authorization_url)
+            .set_revocation_url_option(revocation_url)
+            .set_auth_type(auth_type);
 
-        if let Some(client_secret) = self.client_secret {
-            basic_client = basic_client.set_client_secret(ClientSecret::new(client_secret));
+        if let Some(client_secret) = &client_secret {
+            basic_client = basic_client.set_client_secret(ClientSecret::new(client_secret.clone()));
         }
 
+        #[cfg(feature = "oidc")]
+        let scopes = if self.issuer.is_some() && !self.scopes.iter().any(|s| s.as_str() == "openid")
+        {
+            std::iter::once(Scope::new("openid".into()))
+                .chain(self.scopes)
+                .collect()
+        } else {
+            self.scopes
+        };
+        #[cfg(not(feature = "oidc"))]
+        let scopes = self.scopes;
+
+        #[cfg(feature = "oidc")]
+        let oidc = match self.issuer {
+            Some(issuer) => {
+                let jwks_url = self.jwks_url.ok_or(OAuth2BuilderError::MissingJwksUrl)?;
+                Some(Oidc::new(issuer, jwks_url, self.id_token_algorithms))
+            }
+            None => None,
+        };
+
         Ok(OAuth2Context(Arc::new(OAuth2ContextInner {
             client: basic_client,
             inner,
-            session: self.cookie_builder.try_build()?,
+            session: self
+                .cookie_builder
+                .try_build(is_form_post(&self.authorization_params))?,
+            login_state_store: self.login_state_store,
+            authorization_params: self.authorization_params,
             login_path: self.login_path,
-            http_client: self.http_client.unwrap_or_else(default_reqwest_client),
-            scopes: self.scopes,
+            http_client,
+            scopes,
             flow_type: self.flow_type,
+            userinfo_url: self.userinfo_url,
+            token_store: self.token_store,
+            logout_path: self.logout_path,
+            end_session_url,
+            post_logout_redirect_url: self.post_logout_redirect_url,
+            par_url,
+            client_secret,
+            client_auth: self.client_auth,
+            #[cfg(feature = "oidc")]
+            oidc,
         })))
     }
 }
 
+impl OAuth2ContextBuilder {
+    /// Builds a [`ClientCredentials`] client from the client id, client secret, token url and
+    /// scopes. The other settings are not used.
+    pub fn build_client_credentials(self) -> ClientCredentials {
+        self.try_build_client_credentials().unwrap()
+    }
+
+    pub fn try_build_client_credentials(self) -> Result<ClientCredentials, OAuth2BuilderError> {
+        let (http_client, auth_type, client_secret) = self.client_auth_parts()?;
+
+        let client_id = self
+            .client_id
+            .ok_or(OAuth2BuilderError::MissingClientId)
+            .map(ClientId::new)?;
+
+        let token_url = self.token_url.ok_or(OAuth2BuilderError::MissingTokenUrl)?;
+
+        let token_url = TokenUrl::new(token_url).map_err(OAuth2BuilderError::InvalidTokenUrl)?;
+
+        let mut client = Client::new(client_id)
+            .set_token_uri(token_url)
+            .set_auth_type(auth_type);
+
+        if let Some(client_secret) = client_secret {
+            client = client.set_client_secret(ClientSecret::new(client_secret));
+        }
+
+        Ok(ClientCredentials::new(
+            client,
+            http_client,
+            self.client_auth,
+            self.scopes,
+        ))
+    }
+}
+
 pub(crate) enum FlowType {
     AuthorizationCodeFlow,
     AuthorizationCodeFlowPkce,
@@ -216,7 +591,28 @@ pub enum OAuth2BuilderError {
     InvalidRedirectUrl(url::ParseError),
     InvalidAuthUrl(url::ParseError),
     InvalidTokenUrl(url::ParseError),
+    InvalidDeviceAuthorizationUrl(url::ParseError),
+    InvalidRevocationUrl(url::ParseError),
+    InvalidEndSessionUrl(url::ParseError),
+    InvalidPushedAuthorizationUrl(url::ParseError),
     WhitespaceInProviderName,
+    /// The http client with the client certificate could not be built.
+    HttpClient(String),
+    /// A client certificate is set together with a custom http client, which would not use it.
+    TlsIdentityWithHttpClient,
+    /// Two providers in a registry have the same name.
+    DuplicateProvider(String),
+    /// Two providers in a registry have the same redirect url path.
+    DuplicateRedirectUrl(String),
+    /// The login state store doesn't send its cookie with form_post callbacks, see
+    /// [`LoginStateStore::supports_form_post`].
+    LoginStateStoreWithoutFormPost,
+    /// OpenID Connect is enabled, but no jwks url is set.
+    #[cfg(feature = "oidc")]
+    MissingJwksUrl,
+    /// The OpenID Connect discovery document could not be fetched or is invalid.
+    #[cfg(feature = "oidc")]
+    Discovery(String),
 }
 
 impl Error for OAuth2BuilderError {}
@@ -237,9 +633,42 @@ impl Display for OAuth2BuilderError {
             OAuth2BuilderError::InvalidTokenUrl(parse_error) => {
                 write!(f, "could not parse token url: {}", parse_error)
             }
+            OAuth2BuilderError::InvalidDeviceAuthorizationUrl(parse_error) => {
+                write!(f, "could not parse device authorization url: {parse_error}")
+            }
+            OAuth2BuilderError::InvalidRevocationUrl(parse_error) => {
+                write!(f, "could not parse revocation url: {parse_error}")
+            }
+            OAuth2BuilderError::InvalidEndSessionUrl(parse_error) => {
+                write!(f, "could not parse end session url: {parse_error}")
+            }
+            OAuth2BuilderError::InvalidPushedAuthorizationUrl(parse_error) => {
+                write!(f, "could not parse pushed authorization url: {parse_error}")
+            }
             OAuth2BuilderError::WhitespaceInProviderName => {
                 f.write_str("provider name can't contain whitespaces")
             }
+            OAuth2BuilderError::HttpClient(error) => {
+                write!(f, "could not build http client: {error}")
+            }
+            OAuth2BuilderError::TlsIdentityWithHttpClient => {
+                f.write_str("tls client auth can't be combined with a custom http client")
+            }
+            OAuth2BuilderError::DuplicateProvider(name) => {
+                write!(f, "provider {name} is registered more than once")
+            }
+            OAuth2BuilderError::DuplicateRedirectUrl(path) => {
+                write!(f, "redirect url {path} is used by more than one provider")
+            }
+            OAuth2BuilderError::LoginStateStoreWithoutFormPost => {
+                f.write_str("login state cookie must be SameSite=None and Secure with form_post")
+            }
+            #[cfg(feature = "oidc")]
+            OAuth2BuilderError::MissingJwksUrl => f.write_str("jwks url is missing"),
+            #[cfg(feature = "oidc")]
+            OAuth2BuilderError::Discovery(error) => {
+                write!(f, "openid connect discovery failed: {error}")
+            }
         }
     }
 }
@@ -262,9 +691,12 @@ mod builder {
     struct TestHandler {}
 
     impl OAuth2Handler for TestHandler {
+        type Profile = ();
+
         async fn after_login(
             &self,
             _token_res: TokenResponse,
+            _profile: (),
             _context: &mut AfterLoginCookies<'_>,
         ) -> impl IntoResponse {
             ()
@@ -0,0 +1,64 @@
+#[cfg(feature = "jwt")]
+use jsonwebtoken::{EncodingKey, Header, encode, get_current_timestamp};
+
+/// Client assertions are valid for this many seconds.
+#[cfg(feature = "jwt")]
+const CLIENT_ASSERTION_TTL_SECS: u64 = 60;
+
+#[cfg(feature = "jwt")]
+const CLIENT_ASSERTION_TYPE: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
+/// How the client authenticates to the token endpoint.
+#[derive(Clone, Default)]
+pub(crate) enum ClientAuth {
+    /// The client secret, sent the way the `AuthType` of the client says.
+    #[default]
+    ClientSecret,
+    /// A jwt signed with the private key of the client, RFC 7523.
+    #[cfg(feature = "jwt")]
+    PrivateKeyJwt(Box<AssertionKey>),
+    /// A client certificate on the TLS connection, RFC 8705. Only the client id is sent.
+    Tls,
+}
+
+/// The key and header that client assertions are signed with.
+#[cfg(feature = "jwt")]
+#[derive(Clone)]
+pub(crate) struct AssertionKey {
+    pub(crate) key: EncodingKey,
+    pub(crate) header: Header,
+}
+
+impl ClientAuth {
+    /// The params that authenticate the client in the body of a request to `audience`.
+    pub(crate) fn params(
+        &self,
+        #[cfg_attr(not(feature = "jwt"), allow(unused_variables))] client_id: &str,
+        #[cfg_attr(not(feature = "jwt"), allow(unused_variables))] audience: &str,
+    ) -> Result<Vec<(&'static str, String)>, String> {
+        match self {
+            ClientAuth::ClientSecret | ClientAuth::Tls => Ok(Vec::new()),
+            #[cfg(feature = "jwt")]
+            ClientAuth::PrivateKeyJwt(assertion_key) => {
+                let now = get_current_timestamp();
+
+                let claims = serde_json::json!({
+                    "iss": client_id,
+                    "sub": client_id,
+                    "aud": audience,
+                    "jti": uuid::Uuid::now_v7().to_string(),
+                    "iat": now,
+                    "exp": now + CLIENT_ASSERTION_TTL_SECS,
+                });
+
+                let assertion = encode(&assertion_key.header, &claims, &assertion_key.key)
+                    .map_err(|e| e.to_string())?;
+
+                Ok(vec![
+                    ("client_assertion_type", CLIENT_ASSERTION_TYPE.to_string()),
+                    ("client_assertion", assertion),
+                ])
+            }
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use oauth2::{
+    Scope,
+    reqwest::{
+        Client as HttpClient, Request,
+        header::{AUTHORIZATION, HeaderValue},
+    },
+};
+use tower::{BoxError, Layer, Service};
+
+use crate::{
+    oauth2::{
+        ClientCredentialsClient, ProviderTokens, TokenError, TokenResponse, client_auth::ClientAuth,
+    },
+    utils::utc_now_secs,
+};
+
+/// Fetches access tokens with the client credentials grant, for service to service calls. Tokens
+/// are cached per set of scopes until shortly before they expire, concurrent requests for the
+/// same scopes share one token request.
+///
+/// As a [`Layer`] it adds the access token as bearer token to outbound `reqwest` requests.
+pub struct ClientCredentials {
+    inner: Arc<ClientCredentialsInner>,
+    scopes: Arc<[Scope]>,
+}
+
+/// The cached token for one set of scopes.
+type CacheSlot = Arc<tokio::sync::Mutex<Option<ProviderTokens>>>;
+
+struct ClientCredentialsInner {
+    client: ClientCredentialsClient,
+    http_client: HttpClient,
+    client_auth: ClientAuth,
+    cache: Mutex<HashMap<Vec<String>, CacheSlot>>,
+}
+
+impl ClientCredentials {
+    pub(crate) fn new(
+        client: ClientCredentialsClient,
+        http_client: HttpClient,
+        client_auth: ClientAuth,
+        scopes: Vec<Scope>,
+    ) -> Self {
+        ClientCredentials {
+            inner: Arc::new(ClientCredentialsInner {
+                client,
+                http_client,
+                client_auth,
+                cache: Mutex::new(HashMap::new()),
+            }),
+            scopes: scopes.into(),
+        }
+    }
+
+    /// Returns a client that requests `scopes` instead of the configured scopes, it shares the
+    /// token cache with this client.
+    pub fn with_scopes(&self, scopes: &[&str]) -> Self {
+        ClientCredentials {
+            inner: self.inner.clone(),
+            scopes: scopes.iter().map(|s| Scope::new(s.to_string())).collect(),
+        }
+    }
+
+    /// Returns a cached access token, or requests a new one when it is about to expire.
+    pub async fn access_token(&self) -> Result<String, TokenError> {
+        let slot = self.cache_slot();
+
+        // Holding the lock while requesting a token makes concurrent callers wait for it.
+        let mut cached = slot.lock().await;
+
+        if let Some(tokens) = cached.as_ref()
+            && !tokens.needs_refresh(utc_now_secs())
+        {
+            return Ok(tokens.access_token.clone());
+        }
+
+        tracing::debug!("requesting client credentials token");
+        let client_auth_params = self
+            .inner
+            .client_auth
+            .params(
+                self.inner.client.client_id().as_str(),
+                self.inner.client.token_uri().as_str(),
+            )
+            .map_err(TokenError::ClientCredentials)?;
+
+        let mut request = self
+            .inner
+            .client
+            .exchange_client_credentials()
+            .add_scopes(self.scopes.iter().cloned());
+
+        for (name, value) in client_auth_params {
+            request = request.add_extra_param(name, value);
+        }
+
+        let response = request
+            .request_async(&self.inner.http_client)
+            .await
+            .map_err(|e| TokenError::ClientCredentials(e.to_string()))?;
+
+        let tokens = ProviderTokens::new(&TokenResponse::new(response, &self.scopes));
+        let access_token = tokens.access_token.clone();
+        *cached = Some(tokens);
+
+        Ok(access_token)
+    }
+
+    /// Removes all cached tokens, for example after a token was rejected.
+    pub fn invalidate(&self) {
+        self.inner.cache.lock().unwrap().clear();
+    }
+
+    fn cache_slot(&self) -> CacheSlot {
+        let mut key: Vec<String> = self.scopes.iter().map(|s| s.to_string()).collect();
+        key.sort();
+        key.dedup();
+
+        self.inner
+            .cache
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_default()
+            .clone()
+    }
+}
+
+impl Clone for ClientCredentials {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            scopes: self.scopes.clone(),
+        }
+    }
+}
+
+pub struct ClientCredentialsService<SERV> {
+    credentials: ClientCredentials,
+    rest: SERV,
+}
+
+impl<SERV> Service<Request> for ClientCredentialsService<SERV>
+where
+    SERV: Service<Request> + Clone + Send + 'static,
+    SERV::Error: Into<BoxError>,
+    SERV::Future: Send,
+{
+    type Response = SERV::Response;
+
+    type Error = BoxError;
+
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.rest.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        let credentials = self.credentials.clone();
+        // Take the service that was driven to readiness, leave a clone in its place.
+        let clone = self.rest.clone();
+        let mut rest = std::mem::replace(&mut self.rest, clone);
+
+        Box::pin(async move {
+            // `TokenError` is not `Sync`, so only its message is kept.
+            let access_token = credentials
+                .access_token()
+                .await
+                .map_err(|e| BoxError::from(e.to_string()))?;
+
+            let mut value = HeaderValue::try_from(format!("Bearer {access_token}"))?;
+            value.set_sensitive(true);
+            req.headers_mut().insert(AUTHORIZATION, value);
+
+            rest.call(req).await.map_err(Into::into)
+        })
+    }
+}
+
+impl<SERV> Layer<SERV> for ClientCredentials {
+    type Service = ClientCredentialsService<SERV>;
+
+    fn layer(&self, inner: SERV) -> Self::Service {
+        ClientCredentialsService {
+            credentials: self.clone(),
+            rest: inner,
+        }
+    }
+}
+
+impl<SERV> Clone for ClientCredentialsService<SERV>
+where
+    SERV: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            credentials: self.credentials.clone(),
+            rest: self.rest.clone(),
+        }
+    }
+}
@@ -1,20 +1,32 @@
-use std::{borrow::Cow, convert::Infallible, sync::Arc};
+use std::{any::TypeId, borrow::Cow, convert::Infallible, sync::Arc};
 
 use axum::{
     extract::{FromRef, FromRequestParts},
-    http::{StatusCode, request::Parts},
+    http::request::Parts,
     response::{IntoResponse, Redirect},
 };
 
 use cookie_monster::{CookieBuilder, CookieJar};
 use oauth2::{
-    AuthorizationCode, CsrfToken, PkceCodeChallenge, PkceCodeVerifier, Scope, TokenResponse as _,
+    AuthorizationCode, CsrfToken, PkceCodeChallenge, PkceCodeVerifier, RefreshToken, Scope,
+    url::Url,
 };
+use serde_json::Value;
 
-use crate::oauth2::{
-    AfterLoginCookies, OAuth2ClientTyped, OAuth2Handler, TokenResponse,
-    builder::{FlowType, OAuth2ContextBuilder},
-    cookie::OAuth2Cookie,
+use crate::{
+    cookie::CookieContext,
+    oauth2::{
+        AfterLoginCookies, OAuth2ClientTyped, OAuth2Error, OAuth2Handler, OAuth2TokenResponse,
+        ProviderTokens, TokenError, TokenResponse, UserInfoError,
+        builder::{FlowType, OAuth2ContextBuilder},
+        client_auth::ClientAuth,
+        cookie::OAuth2Cookie,
+        options::{AuthorizationParams, ChallengeOptions, is_form_post, merge_params},
+        redirect::OAuth2Params,
+        state::{DynLoginStateStore, ErasedLoginStateStore, LoginState},
+        userinfo::fetch_userinfo,
+    },
+    utils::utc_now_secs,
 };
 
 pub struct OAuth2Context<H>(pub(super) Arc<OAuth2ContextInner<H>>);
@@ -22,11 +34,23 @@ pub struct OAuth2Context<H>(pub(super) Arc<OAuth2ContextInner<H>>);
 pub(super) struct OAuth2ContextInner<H> {
     pub(super) inner: H,
     pub(super) session: OAuth2Cookie,
+    pub(super) login_state_store: Option<ErasedLoginStateStore>,
+    pub(super) authorization_params: AuthorizationParams,
     pub(super) client: OAuth2ClientTyped,
     pub(super) login_path: Option<Cow<'static, str>>,
     pub(super) scopes: Vec<Scope>,
     pub(super) http_client: ::oauth2::reqwest::Client,
     pub(super) flow_type: FlowType,
+    pub(super) userinfo_url: Option<String>,
+    pub(super) token_store: Option<CookieContext<ProviderTokens>>,
+    pub(super) logout_path: Option<Cow<'static, str>>,
+    pub(super) end_session_url: Option<Url>,
+    pub(super) post_logout_redirect_url: Option<String>,
+    pub(super) par_url: Option<Url>,
+    pub(super) client_secret: Option<String>,
+    pub(super) client_auth: ClientAuth,
+    #[cfg(feature = "oidc")]
+    pub(super) oidc: Option<crate::oauth2::oidc::Oidc>,
 }
 impl OAuth2Context<()> {
     pub fn builder(oauth2_provider_name: impl Into<Cow<'static, str>>) -> OAuth2ContextBuilder {
@@ -34,95 +58,322 @@ impl OAuth2Context<()> {
     }
 }
 
+impl<H> OAuth2Context<H> {
+    /// The name of the provider, as passed to [`OAuth2Context::builder`].
+    pub fn provider_name(&self) -> &str {
+        &self.0.session.provider_name
+    }
+}
+
 impl<H: OAuth2Handler> OAuth2Context<H> {
     pub(crate) fn callback_url(&self) -> &str {
         self.0.client.redirect_uri().unwrap().url().path()
     }
 
+    /// Returns true if the provider sends the callback as a form `POST`.
+    pub(crate) fn uses_form_post(&self) -> bool {
+        is_form_post(&self.0.authorization_params)
+    }
+
     pub(crate) async fn on_redirect(
         &self,
         mut jar: CookieJar,
-        code: AuthorizationCode,
-        state: CsrfToken,
+        params: OAuth2Params,
     ) -> axum::response::Response {
         tracing::debug!("handling redirect");
 
-        let (csrf_token, pkce_verifier) = match self.0.session.verify_cookies(&mut jar) {
-            Ok(Some(session)) => session,
-            Ok(None) => return StatusCode::UNAUTHORIZED.into_response(),
-            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        let mut return_to = None;
+        let result = match self.take_login_state(&mut jar).await {
+            Ok(mut login_state) => {
+                return_to = login_state.return_to.take();
+                self.finish_login(login_state, params).await
+            }
+            Err(error) => Err(error),
         };
 
-        // verify that csrf token is equal
-        if csrf_token.secret() != state.secret() {
-            // bad req
-            tracing::debug!("state does not match");
-            return StatusCode::UNAUTHORIZED.into_response();
-        }
+        self.respond(result, jar, return_to).await
+    }
 
-        // exchange authorization code
-        tracing::debug!("exchanging pkce code for an access token");
-        let token_response = match self.exchange_code(code, pkce_verifier).await {
-            Ok(res) => res,
-            Err(e) => {
-                tracing::debug!("failed to exchange code for access token: {e}");
-                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-            }
+    /// Hands the result of a login flow to the handler.
+    pub(crate) async fn respond(
+        &self,
+        result: Result<(TokenResponse, H::Profile), OAuth2Error>,
+        mut jar: CookieJar,
+        return_to: Option<String>,
+    ) -> axum::response::Response {
+        let result = match result {
+            Ok(login) => self.store_tokens(&login.0, &mut jar).await.map(|_| login),
+            Err(error) => Err(error),
         };
-        // tada, access token, maybe refresh token.
 
-        // after login callback
         let mut context = AfterLoginCookies {
             cookie_jar: jar,
             cookie_opts: &self.0.session.cookie_builder,
+            provider: &self.0.session.provider_name,
+            return_to,
+            added: Vec::new(),
+        };
+
+        let (token_response, profile) = match result {
+            Ok(login) => login,
+            Err(error) => {
+                tracing::debug!("login flow failed: {error}");
+                let res = self
+                    .0
+                    .inner
+                    .on_error(error, &mut context)
+                    .await
+                    .into_response();
+
+                return (context.cookie_jar, res).into_response();
+            }
         };
 
         tracing::debug!("login flow done");
         let res = self
             .0
             .inner
-            .after_login(token_response, &mut context)
+            .after_login(token_response, profile, &mut context)
             .await
             .into_response();
 
+        if !context.added.is_empty() {
+            let added = self.0.session.added_cookies(&context.added);
+            context.cookie_jar.add(added);
+        }
+
         (context.cookie_jar, res).into_response()
     }
 
+    /// The configured login state store, or the signed cookie.
+    fn login_state_store(&self) -> &dyn DynLoginStateStore {
+        match &self.0.login_state_store {
+            Some(store) => store.as_ref(),
+            None => &self.0.session,
+        }
+    }
+
+    async fn take_login_state(&self, jar: &mut CookieJar) -> Result<LoginState, OAuth2Error> {
+        let login_state = self.login_state_store().take(jar).await?;
+        login_state.verify(&self.0.session.provider_name, utc_now_secs())?;
+        Ok(login_state)
+    }
+
+    async fn finish_login(
+        &self,
+        login_state: LoginState,
+        params: OAuth2Params,
+    ) -> Result<(TokenResponse, H::Profile), OAuth2Error> {
+        let Some(state) = params.state else {
+            return Err(OAuth2Error::InvalidCallback);
+        };
+
+        // verify that csrf token is equal, also for errors so they can't be forged.
+        if login_state.csrf_token != *state.secret() {
+            return Err(OAuth2Error::StateMismatch);
+        }
+
+        if let Some(error) = params.error {
+            return Err(OAuth2Error::Provider {
+                error,
+                description: params.error_description,
+                uri: params.error_uri,
+            });
+        }
+
+        let Some(code) = params.code else {
+            return Err(OAuth2Error::InvalidCallback);
+        };
+
+        // exchange authorization code
+        tracing::debug!("exchanging pkce code for an access token");
+        let response = self
+            .exchange_code(code, login_state.pkce_verifier.map(PkceCodeVerifier::new))
+            .await
+            .map_err(OAuth2Error::TokenExchange)?;
+        // tada, access token, maybe refresh token.
+
+        self.complete_login(response, login_state.nonce.as_deref())
+            .await
+    }
+
+    /// Validates the ID token, if OpenID Connect is enabled, and fetches the profile.
+    #[cfg_attr(not(feature = "oidc"), allow(unused_variables))]
+    pub(crate) async fn complete_login(
+        &self,
+        response: OAuth2TokenResponse,
+        nonce: Option<&str>,
+    ) -> Result<(TokenResponse, H::Profile), OAuth2Error> {
+        #[allow(unused_mut)]
+        let mut token_response = TokenResponse::new(response, &self.0.scopes);
+
+        #[cfg(feature = "oidc")]
+        if let Some(oidc) = &self.0.oidc {
+            let claims = oidc
+                .verify_id_token(
+                    &self.0.http_client,
+                    token_response.id_token.as_deref(),
+                    self.0.client.client_id().as_str(),
+                    nonce,
+                )
+                .await
+                .map_err(OAuth2Error::IdToken)?;
+
+            token_response.id_token_claims = Some(claims);
+        }
+
+        let profile = self
+            .fetch_profile(&token_response)
+            .await
+            .map_err(OAuth2Error::UserInfo)?;
+
+        Ok((token_response, profile))
+    }
+
+    /// Saves the tokens in the token store, if there is one, and sets its session cookie.
+    async fn store_tokens(
+        &self,
+        token_response: &TokenResponse,
+        jar: &mut CookieJar,
+    ) -> Result<(), OAuth2Error> {
+        let Some(token_store) = &self.0.token_store else {
+            return Ok(());
+        };
+
+        let cookie = token_store
+            .create_session(ProviderTokens::new(token_response))
+            .await
+            .map_err(OAuth2Error::TokenStore)?;
+
+        jar.add(cookie);
+        Ok(())
+    }
+
+    /// Exchanges `refresh_token` for a new access token. Providers that don't rotate refresh
+    /// tokens leave it out of the response, in that case `refresh_token` is returned again.
+    pub async fn refresh(&self, refresh_token: &str) -> Result<TokenResponse, TokenError> {
+        let refresh_token = RefreshToken::new(refresh_token.to_string());
+
+        let mut request = self.0.client.exchange_refresh_token(&refresh_token);
+        for (name, value) in self.client_auth_params().map_err(TokenError::Refresh)? {
+            request = request.add_extra_param(name, value);
+        }
+
+        let response = request
+            .request_async(&self.0.http_client)
+            .await
+            .map_err(|e| TokenError::Refresh(e.to_string()))?;
+
+        let mut token_response = TokenResponse::new(response, &self.0.scopes);
+        if token_response.refresh_token.is_none() {
+            token_response.refresh_token = Some(refresh_token.into_secret());
+        }
+
+        Ok(token_response)
+    }
+
+    /// Returns a valid access token from the token store, it is refreshed first when it is
+    /// about to expire.
+    pub async fn access_token(&self, jar: &CookieJar) -> Result<String, TokenError> {
+        let Some(token_store) = &self.0.token_store else {
+            return Err(TokenError::NoTokenStore);
+        };
+
+        let Some(mut session) = token_store
+            .load_from_jar(jar)
+            .await
+            .map_err(TokenError::Store)?
+        else {
+            return Err(TokenError::MissingTokens);
+        };
+
+        if !session.state.needs_refresh(utc_now_secs()) {
+            return Ok(session.state.access_token);
+        }
+
+        let Some(refresh_token) = &session.state.refresh_token else {
+            return Err(TokenError::Expired);
+        };
+
+        tracing::debug!("refreshing access token");
+        let token_response = self.refresh(refresh_token).await?;
+
+        let id_token = session.state.id_token.take();
+        session.state = ProviderTokens::new(&token_response);
+        // The ID token is only returned on login by most providers.
+        if session.state.id_token.is_none() {
+            session.state.id_token = id_token;
+        }
+        let access_token = session.state.access_token.clone();
+
+        token_store
+            .store_session(session)
+            .await
+            .map_err(TokenError::Store)?;
+
+        Ok(access_token)
+    }
+
+    /// Fetches the profile from the userinfo endpoint, without a userinfo url or with a `()`
+    /// profile it is deserialized from `null`.
+    async fn fetch_profile(
+        &self,
+        token_response: &TokenResponse,
+    ) -> Result<H::Profile, UserInfoError> {
+        let userinfo_url = match &self.0.userinfo_url {
+            Some(url) if TypeId::of::<H::Profile>() != TypeId::of::<()>() => url,
+            _ => return serde_json::from_value(Value::Null).map_err(UserInfoError::Json),
+        };
+
+        let profile: Value = fetch_userinfo(
+            &self.0.http_client,
+            userinfo_url,
+            &token_response.access_token,
+        )
+        .await?;
+
+        // The userinfo response must be about the user of the ID token.
+        #[cfg(feature = "oidc")]
+        if let Some(claims) = &token_response.id_token_claims
+            && profile.get("sub").and_then(Value::as_str) != Some(claims.sub.as_str())
+        {
+            return Err(UserInfoError::SubjectMismatch);
+        }
+
+        serde_json::from_value(profile).map_err(UserInfoError::Json)
+    }
+
     pub(crate) async fn exchange_code(
         &self,
         code: AuthorizationCode,
         pkce_verifier: Option<PkceCodeVerifier>,
-    ) -> Result<TokenResponse, String> {
-        let response = match self.0.flow_type {
-            FlowType::AuthorizationCodeFlow => self
-                .0
-                .client
-                .exchange_code(code)
-                .request_async(&self.0.http_client)
-                .await
-                .map_err(|e| e.to_string())?,
-            FlowType::AuthorizationCodeFlowPkce => {
-                let Some(pkce_verifier) = pkce_verifier else {
-                    return Err("PKCE code verifier missing from request".into());
-                };
-
-                self.0
-                    .client
-                    .exchange_code(code)
-                    .set_pkce_verifier(pkce_verifier)
-                    .request_async(&self.0.http_client)
-                    .await
-                    .map_err(|e| e.to_string())?
-            }
-        };
+    ) -> Result<OAuth2TokenResponse, String> {
+        let mut request = self.0.client.exchange_code(code);
+
+        if matches!(self.0.flow_type, FlowType::AuthorizationCodeFlowPkce) {
+            let Some(pkce_verifier) = pkce_verifier else {
+                return Err("PKCE code verifier missing from request".into());
+            };
+
+            request = request.set_pkce_verifier(pkce_verifier);
+        }
 
-        let access_token = response.access_token().secret().clone();
-        let refresh_token = response.refresh_token().map(|t| t.secret().clone());
+        for (name, value) in self.client_auth_params()? {
+            request = request.add_extra_param(name, value);
+        }
+
+        request
+            .request_async(&self.0.http_client)
+            .await
+            .map_err(|e| e.to_string())
+    }
 
-        Ok(TokenResponse {
-            access_token,
-            refresh_token,
-        })
+    /// The params that authenticate the client with a client assertion, if configured.
+    pub(crate) fn client_auth_params(&self) -> Result<Vec<(&'static str, String)>, String> {
+        self.0.client_auth.params(
+            self.0.client.client_id().as_str(),
+            self.0.client.token_uri().as_str(),
+        )
     }
 
     pub fn get_start_challenge_path(&self) -> Option<&str> {
@@ -130,7 +381,16 @@ impl<H: OAuth2Handler> OAuth2Context<H> {
     }
 
     pub async fn start_challenge(&self) -> axum::response::Response {
+        self.start_challenge_with(ChallengeOptions::new()).await
+    }
+
+    /// Starts the login flow with extra options, a `&str` or `String` is used as return path.
+    pub async fn start_challenge_with(
+        &self,
+        options: impl Into<ChallengeOptions>,
+    ) -> axum::response::Response {
         tracing::debug!("Starting oauth2 login flow");
+        let options = options.into();
 
         let mut req = self.0.client.authorize_url(CsrfToken::new_random);
 
@@ -148,15 +408,49 @@ impl<H: OAuth2Handler> OAuth2Context<H> {
             None
         };
 
+        #[cfg(feature = "oidc")]
+        let nonce = self.0.oidc.as_ref().map(|_| CsrfToken::new_random());
+        #[cfg(not(feature = "oidc"))]
+        let nonce: Option<CsrfToken> = None;
+
+        if let Some(nonce) = &nonce {
+            req = req.add_extra_param("nonce", nonce.secret().clone());
+        }
+
+        for (name, value) in merge_params(&self.0.authorization_params, &options.params) {
+            req = req.add_extra_param(name.to_string(), value.to_string());
+        }
+
         let (redirect_url, csrf_token) = req.url();
 
-        let cookie = self.0.session.generate_cookie(
-            csrf_token.secret(),
-            pkce_verifier.as_ref().map(|s| s.secret().as_ref()),
+        let redirect_url = match &self.0.par_url {
+            Some(par_url) => match self
+                .push_authorization_request(par_url, &redirect_url)
+                .await
+            {
+                Ok(redirect_url) => redirect_url,
+                Err(error) => {
+                    let error = OAuth2Error::PushedAuthorization(error);
+                    return self.respond(Err(error), CookieJar::new(), None).await;
+                }
+            },
+            None => redirect_url,
+        };
+
+        let login_state = self.0.session.new_login_state(
+            csrf_token.into_secret(),
+            pkce_verifier.map(PkceCodeVerifier::into_secret),
+            nonce.map(CsrfToken::into_secret),
+            options.safe_return_to().map(str::to_string),
         );
 
+        let mut jar = CookieJar::new();
+        if let Err(error) = self.login_state_store().save(login_state, &mut jar).await {
+            return self.respond(Err(error), CookieJar::new(), None).await;
+        }
+
         // Send session cookie back
-        (cookie, Redirect::to(redirect_url.as_str())).into_response()
+        (jar, Redirect::to(redirect_url.as_str())).into_response()
     }
 
     pub fn cookie(&self, name: impl Into<Cow<'static, str>>) -> CookieBuilder {
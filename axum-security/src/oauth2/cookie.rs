@@ -4,12 +4,15 @@ use subtle::ConstantTimeEq;
 use base64::{Engine, prelude::BASE64_STANDARD};
 use cookie_monster::{Cookie, CookieBuilder, CookieJar, SameSite};
 use hmac::{Hmac, Mac};
-use oauth2::{CsrfToken, PkceCodeVerifier};
 use rand::Rng;
 use sha2::Sha256;
 use wincode::{SchemaRead, SchemaWrite};
 
-use crate::{cookie::CookieOptionsBuilder, oauth2::OAuth2BuilderError, utils::utc_now_secs};
+use crate::{
+    cookie::CookieOptionsBuilder,
+    oauth2::{LoginState, LoginStateStore, OAuth2BuilderError, OAuth2Error},
+    utils::utc_now_secs,
+};
 
 const HMAC_HASH_LEN: usize = 32;
 
@@ -17,30 +20,53 @@ const HMAC_HASH_LEN: usize = 32;
 pub struct OAuthState<'a> {
     csrf_token: &'a str,
     pkce_verifier: Option<&'a str>,
+    nonce: Option<&'a str>,
+    return_to: Option<&'a str>,
     provider_name: &'a str,
     issued: u64,
     expires: u64,
 }
 
 pub(crate) struct OAuth2Cookie {
-    provider_name: Cow<'static, str>,
+    pub(crate) provider_name: Cow<'static, str>,
     pub(crate) secret: Hmac<Sha256>,
     pub(crate) cookie_builder: CookieBuilder,
+    /// The options of the login state cookie.
+    login_cookie_builder: CookieBuilder,
     max_login_duration_seconds: u64,
 }
 
 impl OAuth2Cookie {
-    pub fn generate_cookie(&self, csrf_token: &str, pkce_verifier: Option<&str>) -> Cookie {
+    /// The state of a new login flow, it expires after the max login duration.
+    pub(crate) fn new_login_state(
+        &self,
+        csrf_token: String,
+        pkce_verifier: Option<String>,
+        nonce: Option<String>,
+        return_to: Option<String>,
+    ) -> LoginState {
         let issued = utc_now_secs();
-        let expires = issued + self.max_login_duration_seconds;
-        let provider_name = &self.provider_name;
 
-        let state = OAuthState {
+        LoginState {
             csrf_token,
             pkce_verifier,
-            provider_name,
+            nonce,
+            return_to,
+            provider_name: self.provider_name.to_string(),
             issued,
-            expires,
+            expires: issued + self.max_login_duration_seconds,
+        }
+    }
+
+    pub fn generate_cookie(&self, state: &LoginState) -> Cookie {
+        let state = OAuthState {
+            csrf_token: &state.csrf_token,
+            pkce_verifier: state.pkce_verifier.as_deref(),
+            nonce: state.nonce.as_deref(),
+            return_to: state.return_to.as_deref(),
+            provider_name: &state.provider_name,
+            issued: state.issued,
+            expires: state.expires,
         };
 
         let mut data = wincode::serialize(&state).unwrap();
@@ -56,49 +82,67 @@ impl OAuth2Cookie {
         // encode the payload
         let encoded_data = BASE64_STANDARD.encode(data);
 
-        self.cookie_builder.clone().value(encoded_data).build()
+        self.login_cookie_builder
+            .clone()
+            .value(encoded_data)
+            .build()
     }
 
-    pub fn verify_cookies(
-        &self,
-        jar: &mut CookieJar,
-    ) -> Result<Option<(CsrfToken, Option<PkceCodeVerifier>)>, ()> {
-        let Some(cookie) = jar.remove(self.cookie_builder.clone()) else {
+    pub fn verify_cookies(&self, jar: &mut CookieJar) -> Result<LoginState, OAuth2Error> {
+        let Some(cookie) = jar.remove(self.login_cookie_builder.clone()) else {
             // cookie not found
-            return Ok(None);
+            return Err(OAuth2Error::MissingLoginState);
         };
 
-        let now = utc_now_secs();
-
         let Ok(decoded) = BASE64_STANDARD.decode(cookie.value()) else {
             // not valid base64
-            return Err(());
+            return Err(OAuth2Error::InvalidLoginState);
         };
 
         let Some(data) = self.verify_signature(&decoded) else {
-            return Err(());
+            return Err(OAuth2Error::InvalidLoginState);
         };
 
         // deserialize into the state struct.
-        let Ok(data) = wincode::deserialize::<OAuthState>(&data) else {
+        let Ok(data) = wincode::deserialize::<OAuthState>(data) else {
             // could not deserialize state.
-            return Err(());
+            return Err(OAuth2Error::InvalidLoginState);
         };
 
-        if now < data.issued {
-            // went back in time?
-            return Ok(None);
-        }
+        Ok(LoginState {
+            csrf_token: data.csrf_token.into(),
+            pkce_verifier: data.pkce_verifier.map(str::to_string),
+            nonce: data.nonce.map(str::to_string),
+            return_to: data.return_to.map(str::to_string),
+            provider_name: data.provider_name.into(),
+            issued: data.issued,
+            expires: data.expires,
+        })
+    }
+
+    /// Remembers the names of the cookies that were added after login, so they can be removed on
+    /// logout.
+    pub(crate) fn added_cookies(&self, names: &[String]) -> Cookie {
+        self.cookie_builder
+            .clone()
+            .name(self.added_cookies_name())
+            .value(names.join(","))
+            .build()
+    }
+
+    pub(crate) fn remove_added_cookies(&self, jar: &mut CookieJar) {
+        let added = self.cookie_builder.clone().name(self.added_cookies_name());
+        let Some(cookie) = jar.remove(added) else {
+            return;
+        };
 
-        if now > data.expires {
-            // expired
-            return Ok(None);
+        for name in cookie.value().split(',').filter(|name| !name.is_empty()) {
+            jar.remove(self.cookie_builder.clone().name(name.to_string()));
         }
+    }
 
-        Ok(Some((
-            CsrfToken::new(data.csrf_token.into()),
-            data.pkce_verifier.map(|v| PkceCodeVerifier::new(v.into())),
-        )))
+    fn added_cookies_name(&self) -> String {
+        format!("oauth2.cookies.{}", self.provider_name)
     }
 
     fn verify_signature<'a>(&self, data: &'a [u8]) -> Option<&'a [u8]> {
@@ -106,15 +150,14 @@ impl OAuth2Cookie {
             return None;
         }
 
-        let data = &data[..(data.len() - HMAC_HASH_LEN)];
-        let received_signature = &data[(data.len() - HMAC_HASH_LEN)..];
+        let (data, received_signature) = data.split_at(data.len() - HMAC_HASH_LEN);
 
         let mut hmac = self.secret.clone();
 
-        hmac.update(&data);
+        hmac.update(data);
         let signature = hmac.finalize().into_bytes();
 
-        if received_signature.ct_ne(&signature[..]).into() {
+        if received_signature.ct_eq(&signature[..]).into() {
             Some(data)
         } else {
             None
@@ -122,6 +165,18 @@ impl OAuth2Cookie {
     }
 }
 
+/// The default store, the state is kept in an HMAC signed cookie.
+impl LoginStateStore for OAuth2Cookie {
+    async fn save(&self, state: LoginState, jar: &mut CookieJar) -> Result<(), OAuth2Error> {
+        jar.add(self.generate_cookie(&state));
+        Ok(())
+    }
+
+    async fn take(&self, jar: &mut CookieJar) -> Result<LoginState, OAuth2Error> {
+        self.verify_cookies(jar)
+    }
+}
+
 pub(crate) struct OAuthCookieBuilder {
     provider_name: Cow<'static, str>,
     pub(crate) secret: Option<Vec<u8>>,
@@ -165,7 +220,9 @@ impl OAuthCookieBuilder {
             .set_max_age_secs(max_login_duration_seconds);
     }
 
-    pub fn try_build(self) -> Result<OAuth2Cookie, OAuth2BuilderError> {
+    /// With `form_post` the login state cookie is `SameSite=None`, see
+    /// [`OAuth2ContextBuilder::response_mode_form_post`](crate::oauth2::OAuth2ContextBuilder::response_mode_form_post).
+    pub fn try_build(self, form_post: bool) -> Result<OAuth2Cookie, OAuth2BuilderError> {
         if self
             .provider_name
             .find(|c: char| c.is_whitespace())
@@ -186,11 +243,65 @@ impl OAuthCookieBuilder {
 
         let cookie_builder = self.cookie_builder.build();
 
+        // The provider posts the callback from its own site, browsers only send cookies with
+        // cross-site posts when they are `SameSite=None`, which requires `Secure`.
+        let login_cookie_builder = if form_post {
+            cookie_builder.clone().same_site(SameSite::None).secure()
+        } else {
+            cookie_builder.clone()
+        };
+
         Ok(OAuth2Cookie {
             provider_name: self.provider_name,
             secret,
             cookie_builder,
+            login_cookie_builder,
             max_login_duration_seconds: self.max_login_duration_seconds,
         })
     }
 }
+
+#[cfg(test)]
+mod signature {
+    use hmac::Mac;
+
+    use crate::oauth2::cookie::{HMAC_HASH_LEN, OAuthCookieBuilder};
+
+    #[test]
+    fn verify_signature() {
+        let cookie = OAuthCookieBuilder::new("test".into())
+            .try_build(false)
+            .unwrap();
+
+        let sign = |data: &[u8]| {
+            let mut hmac = cookie.secret.clone();
+            hmac.update(data);
+            let mut signed = data.to_vec();
+            signed.extend_from_slice(&hmac.finalize().into_bytes());
+            signed
+        };
+
+        let signed = sign(b"login state");
+        assert_eq!(cookie.verify_signature(&signed), Some(&b"login state"[..]));
+
+        // The data is changed.
+        let mut tampered = signed.clone();
+        tampered[0] ^= 1;
+        assert_eq!(cookie.verify_signature(&tampered), None);
+
+        // The signature is changed.
+        let mut tampered = signed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert_eq!(cookie.verify_signature(&tampered), None);
+
+        // No signature at all.
+        assert_eq!(cookie.verify_signature(&[7; 2 * HMAC_HASH_LEN]), None);
+        assert_eq!(cookie.verify_signature(b"short"), None);
+
+        // Signed with another secret.
+        let other = OAuthCookieBuilder::new("test".into())
+            .try_build(false)
+            .unwrap();
+        assert_eq!(other.verify_signature(&signed), None);
+    }
+}
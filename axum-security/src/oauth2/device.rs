@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+use cookie_monster::CookieJar;
+use oauth2::{DeviceCodeErrorResponseType, RequestTokenError, StandardDeviceAuthorizationResponse};
+
+use crate::oauth2::{OAuth2Context, OAuth2Error, OAuth2Handler, OAuth2TokenResponse};
+
+/// A started device authorization grant (RFC 8628). Show [`verification_uri`](Self::verification_uri)
+/// and [`user_code`](Self::user_code) to the user, then wait for the login with
+/// [`OAuth2Context::finish_device_authorization`].
+#[derive(Debug, Clone)]
+pub struct DeviceAuthorization(StandardDeviceAuthorizationResponse);
+
+impl DeviceAuthorization {
+    /// The url where the user enters the user code.
+    pub fn verification_uri(&self) -> &str {
+        self.0.verification_uri()
+    }
+
+    /// The verification url with the user code already filled in, for example for a QR code.
+    pub fn verification_uri_complete(&self) -> Option<&str> {
+        self.0
+            .verification_uri_complete()
+            .map(|uri| uri.secret().as_str())
+    }
+
+    pub fn user_code(&self) -> &str {
+        self.0.user_code().secret()
+    }
+
+    /// How long the user code is valid.
+    pub fn expires_in(&self) -> Duration {
+        self.0.expires_in()
+    }
+
+    /// How long to wait between polls of the token endpoint.
+    pub fn interval(&self) -> Duration {
+        self.0.interval()
+    }
+}
+
+impl<H: OAuth2Handler> OAuth2Context<H> {
+    /// Starts the device authorization grant, this needs a
+    /// [`device_authorization_url`](crate::oauth2::OAuth2ContextBuilder::device_authorization_url).
+    pub async fn start_device_authorization(&self) -> Result<DeviceAuthorization, OAuth2Error> {
+        tracing::debug!("starting device authorization");
+
+        let mut request = self
+            .0
+            .client
+            .exchange_device_code()
+            .map_err(|e| OAuth2Error::DeviceAuthorization(e.to_string()))?
+            .add_scopes(self.0.scopes.clone());
+
+        for (name, value) in self
+            .client_auth_params()
+            .map_err(OAuth2Error::DeviceAuthorization)?
+        {
+            request = request.add_extra_param(name, value);
+        }
+
+        let response = request
+            .request_async(&self.0.http_client)
+            .await
+            .map_err(|e| OAuth2Error::DeviceAuthorization(e.to_string()))?;
+
+        Ok(DeviceAuthorization(response))
+    }
+
+    /// Polls the token endpoint until the user approved or denied the login, or the user code
+    /// expired. `slow_down` responses and connection errors increase the poll interval. The result
+    /// is handed to the handler like a browser login, cookies end up in the returned response.
+    pub async fn finish_device_authorization(
+        &self,
+        device: &DeviceAuthorization,
+    ) -> axum::response::Response {
+        let result = match self.poll_device_token(device).await {
+            Ok(response) => self.complete_login(response, None).await,
+            Err(error) => Err(error),
+        };
+
+        self.respond(result, CookieJar::new(), None).await
+    }
+
+    async fn poll_device_token(
+        &self,
+        device: &DeviceAuthorization,
+    ) -> Result<OAuth2TokenResponse, OAuth2Error> {
+        let mut request = self.0.client.exchange_device_access_token(&device.0);
+        for (name, value) in self
+            .client_auth_params()
+            .map_err(OAuth2Error::TokenExchange)?
+        {
+            request = request.add_extra_param(name, value);
+        }
+
+        request
+            .request_async(&self.0.http_client, tokio::time::sleep, None)
+            .await
+            .map_err(|e| match e {
+                RequestTokenError::ServerResponse(response) => match response.error() {
+                    DeviceCodeErrorResponseType::ExpiredToken => OAuth2Error::LoginExpired,
+                    error => OAuth2Error::Provider {
+                        error: error.to_string(),
+                        description: response.error_description().cloned(),
+                        uri: response.error_uri().cloned(),
+                    },
+                },
+                e => OAuth2Error::TokenExchange(e.to_string()),
+            })
+    }
+}
@@ -0,0 +1,115 @@
+use std::{error::Error, fmt::Display};
+
+use axum::http::StatusCode;
+
+#[cfg(feature = "oidc")]
+use crate::oauth2::OidcError;
+use crate::oauth2::UserInfoError;
+
+/// The reason a login flow failed, see [`OAuth2Handler::on_error`](crate::oauth2::OAuth2Handler::on_error).
+#[derive(Debug)]
+pub enum OAuth2Error {
+    /// The provider redirected back with an error, for example `access_denied` when the user
+    /// declined the login.
+    Provider {
+        error: String,
+        description: Option<String>,
+        uri: Option<String>,
+    },
+    /// The callback has no `code` or `state`.
+    InvalidCallback,
+    /// No login flow was started, or the login cookie was not sent.
+    MissingLoginState,
+    /// The login cookie is tampered with or could not be read.
+    InvalidLoginState,
+    /// The login flow took longer than the max login duration.
+    LoginExpired,
+    /// The `state` of the callback doesn't match the login flow.
+    StateMismatch,
+    /// The login flow was started for another provider.
+    ProviderMismatch,
+    /// The authorization code could not be exchanged for an access token.
+    TokenExchange(String),
+    /// The device authorization could not be started.
+    DeviceAuthorization(String),
+    /// The pushed authorization request was rejected.
+    PushedAuthorization(String),
+    /// The ID token is missing or invalid.
+    #[cfg(feature = "oidc")]
+    IdToken(OidcError),
+    /// The profile could not be fetched from the userinfo endpoint.
+    UserInfo(UserInfoError),
+    /// The provider tokens could not be saved in the token store.
+    TokenStore(Box<dyn Error + Send>),
+    /// The login state could not be saved or loaded.
+    LoginStateStore(Box<dyn Error + Send>),
+}
+
+impl OAuth2Error {
+    /// The status code that is returned by the default error handler.
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            OAuth2Error::InvalidCallback => StatusCode::BAD_REQUEST,
+            OAuth2Error::Provider { .. }
+            | OAuth2Error::MissingLoginState
+            | OAuth2Error::InvalidLoginState
+            | OAuth2Error::LoginExpired
+            | OAuth2Error::StateMismatch
+            | OAuth2Error::ProviderMismatch => StatusCode::UNAUTHORIZED,
+            #[cfg(feature = "oidc")]
+            OAuth2Error::IdToken(_) => StatusCode::UNAUTHORIZED,
+            OAuth2Error::TokenExchange(_)
+            | OAuth2Error::DeviceAuthorization(_)
+            | OAuth2Error::PushedAuthorization(_)
+            | OAuth2Error::UserInfo(_)
+            | OAuth2Error::TokenStore(_)
+            | OAuth2Error::LoginStateStore(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl Error for OAuth2Error {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            #[cfg(feature = "oidc")]
+            OAuth2Error::IdToken(error) => Some(error),
+            OAuth2Error::UserInfo(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl Display for OAuth2Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OAuth2Error::Provider {
+                error, description, ..
+            } => match description {
+                Some(description) => write!(f, "provider returned {error}: {description}"),
+                None => write!(f, "provider returned {error}"),
+            },
+            OAuth2Error::InvalidCallback => f.write_str("callback has no code or state"),
+            OAuth2Error::MissingLoginState => f.write_str("login cookie is missing"),
+            OAuth2Error::InvalidLoginState => f.write_str("login cookie is invalid"),
+            OAuth2Error::LoginExpired => f.write_str("login flow expired"),
+            OAuth2Error::StateMismatch => f.write_str("state does not match"),
+            OAuth2Error::ProviderMismatch => {
+                f.write_str("login flow was started for another provider")
+            }
+            OAuth2Error::TokenExchange(error) => {
+                write!(f, "failed to exchange code for access token: {error}")
+            }
+            OAuth2Error::DeviceAuthorization(error) => {
+                write!(f, "failed to start device authorization: {error}")
+            }
+            OAuth2Error::PushedAuthorization(error) => {
+                write!(f, "failed to push authorization request: {error}")
+            }
+            #[cfg(feature = "oidc")]
+            OAuth2Error::IdToken(error) => error.fmt(f),
+            OAuth2Error::UserInfo(error) => error.fmt(f),
+            OAuth2Error::TokenStore(error) => write!(f, "token store error: {error}"),
+            OAuth2Error::LoginStateStore(error) => write!(f, "login state store error: {error}"),
+        }
+    }
+}
@@ -1,37 +1,192 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, sync::Arc, time::Duration};
 
 use axum::response::IntoResponse;
 use cookie_monster::{Cookie, CookieBuilder, CookieJar};
+use oauth2::{Scope, TokenResponse as _};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
 
+#[cfg(feature = "oidc")]
+use crate::oauth2::IdTokenClaims;
+use crate::oauth2::{OAuth2Error, OAuth2TokenResponse};
+
+#[derive(Debug, Clone)]
 pub struct TokenResponse {
     pub access_token: String,
+    /// The token type, usually `Bearer`.
+    pub token_type: String,
     pub refresh_token: Option<String>,
+    /// How long the access token is valid, if the provider returned it.
+    pub expires_in: Option<Duration>,
+    /// The granted scopes. When the provider omits them, these are the requested scopes
+    /// (RFC 6749, section 5.1).
+    pub scopes: Vec<String>,
+    /// The encoded ID token, if the provider returned one.
+    pub id_token: Option<String>,
+    /// The claims of the validated ID token, when OpenID Connect is enabled.
+    #[cfg(feature = "oidc")]
+    pub id_token_claims: Option<IdTokenClaims>,
+    /// All other fields of the token response.
+    pub extra: Map<String, Value>,
+}
+
+impl TokenResponse {
+    pub(crate) fn new(response: OAuth2TokenResponse, requested_scopes: &[Scope]) -> Self {
+        let extra = response.extra_fields().clone();
+
+        TokenResponse {
+            access_token: response.access_token().secret().clone(),
+            token_type: response.token_type().as_ref().to_string(),
+            refresh_token: response.refresh_token().map(|t| t.secret().clone()),
+            expires_in: response.expires_in(),
+            scopes: response
+                .scopes()
+                .map_or(requested_scopes, |scopes| scopes.as_slice())
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            id_token: extra.id_token,
+            #[cfg(feature = "oidc")]
+            id_token_claims: None,
+            extra: extra.extra,
+        }
+    }
+
+    /// Returns true if `scope` was granted.
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope)
+    }
 }
 
 pub trait OAuth2Handler: Send + Sync + 'static {
+    /// The user profile returned by the userinfo endpoint. When no userinfo url is configured,
+    /// the profile is deserialized from `null`, so `()` or an `Option` can be used. A `()`
+    /// profile never requests the userinfo endpoint.
+    type Profile: DeserializeOwned + Send + 'static;
+
     fn after_login(
         &self,
         token_res: TokenResponse,
+        profile: Self::Profile,
         _context: &mut AfterLoginCookies<'_>,
     ) -> impl Future<Output = impl IntoResponse> + Send;
+
+    /// Called when the login flow fails. Returns the status code of the error by default.
+    fn on_error(
+        &self,
+        error: OAuth2Error,
+        _context: &mut AfterLoginCookies<'_>,
+    ) -> impl Future<Output = impl IntoResponse> + Send {
+        async move { error.status_code() }
+    }
+}
+
+/// A handler that is shared between providers, see [`OAuth2Registry`](crate::oauth2::OAuth2Registry).
+impl<H: OAuth2Handler> OAuth2Handler for Arc<H> {
+    type Profile = H::Profile;
+
+    fn after_login(
+        &self,
+        token_res: TokenResponse,
+        profile: Self::Profile,
+        context: &mut AfterLoginCookies<'_>,
+    ) -> impl Future<Output = impl IntoResponse> + Send {
+        (**self).after_login(token_res, profile, context)
+    }
+
+    fn on_error(
+        &self,
+        error: OAuth2Error,
+        context: &mut AfterLoginCookies<'_>,
+    ) -> impl Future<Output = impl IntoResponse> + Send {
+        (**self).on_error(error, context)
+    }
 }
 
 pub struct AfterLoginCookies<'a> {
     pub(crate) cookie_jar: CookieJar,
     pub(crate) cookie_opts: &'a CookieBuilder,
+    pub(crate) provider: &'a str,
+    pub(crate) return_to: Option<String>,
+    /// The names of the added cookies, they are removed on logout.
+    pub(crate) added: Vec<String>,
 }
 
 impl AfterLoginCookies<'_> {
+    /// The name of the provider the user logged in with.
+    pub fn provider(&self) -> &str {
+        self.provider
+    }
+
+    /// The same origin path that was passed to
+    /// [`start_challenge_with`](crate::oauth2::OAuth2Context::start_challenge_with).
+    pub fn return_to(&self) -> Option<&str> {
+        self.return_to.as_deref()
+    }
+
     pub fn cookie(&self, name: impl Into<Cow<'static, str>>) -> CookieBuilder {
         self.cookie_opts.clone().name(name)
     }
 
     pub fn remove(&mut self, name: impl Into<Cow<'static, str>>) -> Option<Cookie> {
         let cookie = self.cookie(name);
+        self.added.retain(|added| added != cookie.get_name());
         self.cookie_jar.remove(cookie)
     }
 
     pub fn add(&mut self, cookie: impl Into<Cookie>) {
-        self.cookie_jar.add(cookie.into());
+        let cookie = cookie.into();
+        if !self.added.iter().any(|added| added == cookie.name()) {
+            self.added.push(cookie.name().to_string());
+        }
+        self.cookie_jar.add(cookie);
+    }
+}
+
+#[cfg(test)]
+mod token_response {
+    use oauth2::Scope;
+
+    use crate::oauth2::{OAuth2TokenResponse, TokenResponse};
+
+    fn response(json: serde_json::Value) -> OAuth2TokenResponse {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn granted_scopes() {
+        let requested = [
+            Scope::new("user:email".into()),
+            Scope::new("read:org".into()),
+        ];
+
+        let token_res = TokenResponse::new(
+            response(serde_json::json!({
+                "access_token": "token",
+                "token_type": "bearer",
+                "scope": "user:email",
+            })),
+            &requested,
+        );
+
+        assert!(token_res.has_scope("user:email"));
+        assert!(!token_res.has_scope("read:org"));
+    }
+
+    #[test]
+    fn omitted_scopes_are_the_requested_scopes() {
+        let requested = [Scope::new("user:email".into())];
+
+        let token_res = TokenResponse::new(
+            response(serde_json::json!({
+                "access_token": "token",
+                "token_type": "bearer",
+            })),
+            &requested,
+        );
+
+        assert_eq!(token_res.scopes, ["user:email"]);
+        assert!(token_res.has_scope("user:email"));
+        assert!(!token_res.has_scope("repo"));
     }
 }
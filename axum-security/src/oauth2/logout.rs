@@ -0,0 +1,106 @@
+use axum::{
+    Extension,
+    response::{IntoResponse, Redirect},
+};
+use cookie_monster::CookieJar;
+use oauth2::{AccessToken, RefreshToken, StandardRevocableToken};
+
+use crate::oauth2::{OAuth2Context, OAuth2Handler, ProviderTokens, TokenError};
+
+impl<H: OAuth2Handler> OAuth2Context<H> {
+    pub fn get_logout_path(&self) -> Option<&str> {
+        self.0.logout_path.as_deref()
+    }
+
+    /// Revokes the refresh token, or the access token when there is no refresh token, at the
+    /// revocation url (RFC 7009).
+    pub async fn revoke(&self, tokens: &ProviderTokens) -> Result<(), TokenError> {
+        let token = match &tokens.refresh_token {
+            Some(refresh_token) => {
+                StandardRevocableToken::RefreshToken(RefreshToken::new(refresh_token.clone()))
+            }
+            None => {
+                StandardRevocableToken::AccessToken(AccessToken::new(tokens.access_token.clone()))
+            }
+        };
+
+        let mut request = self
+            .0
+            .client
+            .revoke_token(token)
+            .map_err(|e| TokenError::Revocation(e.to_string()))?;
+
+        for (name, value) in self.client_auth_params().map_err(TokenError::Revocation)? {
+            request = request.add_extra_param(name, value);
+        }
+
+        request
+            .request_async(&self.0.http_client)
+            .await
+            .map_err(|e| TokenError::Revocation(e.to_string()))
+    }
+
+    /// Logs the user out. The provider tokens are revoked and removed from the token store, the
+    /// cookies that were added with [`AfterLoginCookies`](crate::oauth2::AfterLoginCookies) are
+    /// removed. Then the user is redirected to the end session url of the provider, or to the
+    /// post logout redirect url.
+    pub async fn logout(&self, mut jar: CookieJar) -> axum::response::Response {
+        tracing::debug!("logging out");
+        let mut id_token = None;
+
+        if let Some(token_store) = &self.0.token_store {
+            match token_store.remove_session_jar(&jar).await {
+                Ok(Some(session)) => {
+                    if self.0.client.revocation_url().is_some()
+                        && let Err(error) = self.revoke(&session.state).await
+                    {
+                        tracing::debug!("{error}");
+                    }
+
+                    id_token = session.state.id_token;
+                }
+                Ok(None) => {}
+                Err(error) => tracing::debug!("failed to remove provider tokens: {error}"),
+            }
+
+            jar.remove(token_store.cookie_builder().clone());
+        }
+
+        self.0.session.remove_added_cookies(&mut jar);
+
+        (jar, Redirect::to(&self.logout_url(id_token.as_deref()))).into_response()
+    }
+
+    /// The end session url with `id_token_hint` and `post_logout_redirect_uri`, when OpenID
+    /// Connect logout is configured.
+    fn logout_url(&self, id_token: Option<&str>) -> String {
+        let post_logout_redirect_url = self.0.post_logout_redirect_url.as_deref();
+
+        let Some(end_session_url) = &self.0.end_session_url else {
+            return post_logout_redirect_url.unwrap_or("/").to_string();
+        };
+
+        let mut url = end_session_url.clone();
+        {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("client_id", self.0.client.client_id().as_str());
+
+            if let Some(id_token) = id_token {
+                query.append_pair("id_token_hint", id_token);
+            }
+
+            if let Some(post_logout_redirect_url) = post_logout_redirect_url {
+                query.append_pair("post_logout_redirect_uri", post_logout_redirect_url);
+            }
+        }
+
+        url.into()
+    }
+}
+
+pub(crate) async fn logout<H: OAuth2Handler>(
+    Extension(context): Extension<OAuth2Context<H>>,
+    jar: CookieJar,
+) -> impl IntoResponse {
+    context.logout(jar).await
+}
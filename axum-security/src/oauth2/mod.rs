@@ -1,18 +1,86 @@
 mod builder;
+mod client_auth;
+mod client_credentials;
 mod context;
 mod cookie;
+mod device;
+mod error;
 mod handler;
+mod logout;
+#[cfg(feature = "oidc")]
+mod oidc;
+mod options;
+mod par;
 pub mod providers;
 mod redirect;
+mod registry;
 mod router;
+mod state;
+mod tokens;
+mod userinfo;
 
-pub use builder::OAuth2BuilderError;
+pub use builder::{OAuth2BuilderError, OAuth2ContextBuilder};
+pub use client_credentials::{ClientCredentials, ClientCredentialsService};
 pub use context::OAuth2Context;
+pub use device::DeviceAuthorization;
+pub use error::OAuth2Error;
 pub use handler::{AfterLoginCookies, OAuth2Handler, TokenResponse};
+#[cfg(feature = "oidc")]
+pub use oidc::{IdTokenClaims, OidcError};
+pub use options::ChallengeOptions;
 pub(crate) use redirect::{on_redirect, start_login};
+pub use registry::{OAuth2Registry, OAuth2RegistryBuilder};
 pub use router::OAuth2Ext;
+pub use state::{LoginState, LoginStateStore};
+pub use tokens::{AccessToken, ProviderTokens, TokenError};
+pub use userinfo::UserInfoError;
 
-use oauth2::{EndpointNotSet, EndpointSet, basic::BasicClient};
+use oauth2::{
+    Client, EndpointMaybeSet, EndpointNotSet, EndpointSet, ExtraTokenFields,
+    StandardRevocableToken, StandardTokenResponse,
+    basic::{
+        BasicErrorResponse, BasicRevocationErrorResponse, BasicTokenIntrospectionResponse,
+        BasicTokenType,
+    },
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
-pub(crate) type OAuth2ClientTyped =
-    BasicClient<EndpointSet, EndpointNotSet, EndpointNotSet, EndpointNotSet, EndpointSet>;
+/// Token response fields that are not part of RFC 6749.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct TokenFields {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) id_token: Option<String>,
+    #[serde(flatten)]
+    pub(crate) extra: Map<String, Value>,
+}
+
+impl ExtraTokenFields for TokenFields {}
+
+pub(crate) type OAuth2TokenResponse = StandardTokenResponse<TokenFields, BasicTokenType>;
+
+pub(crate) type OAuth2ClientTyped = Client<
+    BasicErrorResponse,
+    OAuth2TokenResponse,
+    BasicTokenIntrospectionResponse,
+    StandardRevocableToken,
+    BasicRevocationErrorResponse,
+    EndpointSet,
+    EndpointMaybeSet,
+    EndpointNotSet,
+    EndpointMaybeSet,
+    EndpointSet,
+>;
+
+pub(crate) type ClientCredentialsClient = Client<
+    BasicErrorResponse,
+    OAuth2TokenResponse,
+    BasicTokenIntrospectionResponse,
+    StandardRevocableToken,
+    BasicRevocationErrorResponse,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointSet,
+>;
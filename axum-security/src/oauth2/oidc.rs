@@ -0,0 +1,283 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+
+use jsonwebtoken::{
+    Algorithm, DecodingKey, Validation, decode, decode_header,
+    errors::Error as JwtError,
+    jwk::{Jwk, JwkSet},
+};
+use oauth2::reqwest::Client as HttpClient;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::jwt::Audience;
+
+/// The claims of an ID token, see the OpenID Connect Core spec, section 2.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: Audience,
+    pub exp: u64,
+    pub iat: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_time: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub azp: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email_verified: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// All other claims in the token.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// The parts of `.well-known/openid-configuration` that are used.
+#[derive(Deserialize)]
+pub(crate) struct ProviderMetadata {
+    pub(crate) issuer: String,
+    pub(crate) authorization_endpoint: String,
+    pub(crate) token_endpoint: String,
+    pub(crate) jwks_uri: String,
+    #[serde(default)]
+    pub(crate) id_token_signing_alg_values_supported: Vec<String>,
+    pub(crate) revocation_endpoint: Option<String>,
+    pub(crate) end_session_endpoint: Option<String>,
+    pub(crate) pushed_authorization_request_endpoint: Option<String>,
+    #[serde(default)]
+    pub(crate) require_pushed_authorization_requests: bool,
+}
+
+impl ProviderMetadata {
+    pub(crate) async fn discover(http_client: &HttpClient, issuer: &str) -> Result<Self, String> {
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            issuer.trim_end_matches('/')
+        );
+
+        let metadata: ProviderMetadata = get_json(http_client, &url).await?;
+
+        // The issuer in the document must be exactly the issuer that was used for discovery.
+        if metadata.issuer != issuer {
+            return Err(format!(
+                "issuer {} does not match discovered issuer {}",
+                issuer, metadata.issuer
+            ));
+        }
+
+        Ok(metadata)
+    }
+
+    /// Supported signing algorithms, `RS256` is always supported.
+    pub(crate) fn algorithms(&self) -> Vec<Algorithm> {
+        let mut algorithms = vec![Algorithm::RS256];
+
+        for alg in &self.id_token_signing_alg_values_supported {
+            if let Ok(alg) = Algorithm::from_str(alg)
+                && !algorithms.contains(&alg)
+            {
+                algorithms.push(alg);
+            }
+        }
+
+        algorithms
+    }
+}
+
+pub(crate) struct Oidc {
+    pub(crate) issuer: String,
+    pub(crate) jwks_url: String,
+    pub(crate) algorithms: Vec<Algorithm>,
+    jwks: RwLock<Option<Arc<JwkSet>>>,
+}
+
+/// The reason an ID token was rejected.
+#[derive(Debug)]
+pub enum OidcError {
+    MissingIdToken,
+    Jwks(String),
+    UnknownKey,
+    InvalidIdToken(JwtError),
+    InvalidAlgorithm,
+    InvalidAuthorizedParty,
+    NonceMismatch,
+}
+
+impl Error for OidcError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            OidcError::InvalidIdToken(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl Display for OidcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OidcError::MissingIdToken => f.write_str("token response contains no id token"),
+            OidcError::Jwks(error) => write!(f, "could not fetch jwks: {error}"),
+            OidcError::UnknownKey => f.write_str("id token is signed with an unknown key"),
+            OidcError::InvalidIdToken(error) => write!(f, "invalid id token: {error}"),
+            OidcError::InvalidAlgorithm => f.write_str("id token algorithm is not allowed"),
+            OidcError::InvalidAuthorizedParty => f.write_str("id token azp does not match"),
+            OidcError::NonceMismatch => f.write_str("id token nonce does not match"),
+        }
+    }
+}
+
+impl Oidc {
+    pub(crate) fn new(issuer: String, jwks_url: String, algorithms: Vec<Algorithm>) -> Self {
+        Self {
+            issuer,
+            jwks_url,
+            algorithms,
+            jwks: RwLock::new(None),
+        }
+    }
+
+    /// Validates the signature and the claims of an ID token.
+    pub(crate) async fn verify_id_token(
+        &self,
+        http_client: &HttpClient,
+        id_token: Option<&str>,
+        client_id: &str,
+        nonce: Option<&str>,
+    ) -> Result<IdTokenClaims, OidcError> {
+        let raw = id_token.ok_or(OidcError::MissingIdToken)?;
+        let header = decode_header(raw).map_err(OidcError::InvalidIdToken)?;
+
+        if !self.algorithms.contains(&header.alg) {
+            return Err(OidcError::InvalidAlgorithm);
+        }
+
+        let key = self.key(http_client, header.kid.as_deref()).await?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.leeway = 60;
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[client_id]);
+        validation.set_required_spec_claims(&["exp", "iat", "iss", "aud", "sub"]);
+
+        let claims = decode::<IdTokenClaims>(raw, &key, &validation)
+            .map_err(OidcError::InvalidIdToken)?
+            .claims;
+
+        // When the token has multiple audiences, the authorized party must be this client.
+        let multiple_audiences = matches!(&claims.aud, Audience::Multiple(aud) if aud.len() > 1);
+        match claims.azp.as_deref() {
+            Some(azp) if azp != client_id => return Err(OidcError::InvalidAuthorizedParty),
+            None if multiple_audiences => return Err(OidcError::InvalidAuthorizedParty),
+            _ => {}
+        }
+
+        if nonce.is_some() && claims.nonce.as_deref() != nonce {
+            return Err(OidcError::NonceMismatch);
+        }
+
+        Ok(claims)
+    }
+
+    /// Looks up the key in the cached JWKS, the JWKS is fetched again when the key is unknown.
+    async fn key(
+        &self,
+        http_client: &HttpClient,
+        kid: Option<&str>,
+    ) -> Result<DecodingKey, OidcError> {
+        let cached = self.jwks.read().unwrap().clone();
+
+        if let Some(jwk) = cached.as_deref().and_then(|jwks| find_key(jwks, kid)) {
+            return DecodingKey::from_jwk(jwk).map_err(OidcError::InvalidIdToken);
+        }
+
+        tracing::debug!("fetching jwks from {}", self.jwks_url);
+        let jwks: Arc<JwkSet> = Arc::new(
+            get_json(http_client, &self.jwks_url)
+                .await
+                .map_err(OidcError::Jwks)?,
+        );
+        *self.jwks.write().unwrap() = Some(jwks.clone());
+
+        let jwk = find_key(&jwks, kid).ok_or(OidcError::UnknownKey)?;
+        DecodingKey::from_jwk(jwk).map_err(OidcError::InvalidIdToken)
+    }
+}
+
+/// Finds the key with `kid`, a token without `kid` can only be used when there is one key.
+fn find_key<'a>(jwks: &'a JwkSet, kid: Option<&str>) -> Option<&'a Jwk> {
+    match kid {
+        Some(kid) => jwks.find(kid),
+        None if jwks.keys.len() == 1 => jwks.keys.first(),
+        None => None,
+    }
+}
+
+async fn get_json<T: serde::de::DeserializeOwned>(
+    http_client: &HttpClient,
+    url: &str,
+) -> Result<T, String> {
+    let response = http_client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !response.status().is_success() {
+        return Err(format!("{url} returned {}", response.status()));
+    }
+
+    let body = response.bytes().await.map_err(|e| e.to_string())?;
+    serde_json::from_slice(&body).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod oidc_test {
+    use jsonwebtoken::{Algorithm, jwk::JwkSet};
+
+    use crate::oauth2::oidc::{ProviderMetadata, find_key};
+
+    #[test]
+    fn algorithms() {
+        let metadata: ProviderMetadata = serde_json::from_str(
+            r#"{
+                "issuer": "https://example.com",
+                "authorization_endpoint": "https://example.com/authorize",
+                "token_endpoint": "https://example.com/token",
+                "jwks_uri": "https://example.com/jwks",
+                "id_token_signing_alg_values_supported": ["ES256", "RS256", "none"]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            metadata.algorithms(),
+            vec![Algorithm::RS256, Algorithm::ES256]
+        );
+    }
+
+    #[test]
+    fn find_keys() {
+        let key = r#"{"kty":"EC","crv":"P-256","kid":"a","x":"dBDjL_39P0ItVmGmvmFawkvHWL1VL8Ucl58sE6tMjJ4","y":"5Tekv1Z_nT-tTcX1fLTth1NkpLQqhPInxPI7NIb_yWg"}"#;
+        let one: JwkSet = serde_json::from_str(&format!(r#"{{"keys":[{key}]}}"#)).unwrap();
+        let two: JwkSet = serde_json::from_str(&format!(
+            r#"{{"keys":[{key},{}]}}"#,
+            key.replace("\"a\"", "\"b\"")
+        ))
+        .unwrap();
+
+        assert!(find_key(&one, Some("a")).is_some());
+        assert!(find_key(&one, Some("b")).is_none());
+        assert!(find_key(&one, None).is_some());
+        assert!(find_key(&two, Some("b")).is_some());
+        assert!(find_key(&two, None).is_none());
+    }
+}
@@ -0,0 +1,218 @@
+use std::borrow::Cow;
+
+const MAX_RETURN_PATH_LEN: usize = 2048;
+
+/// Params of the authorization request that are set by the login flow itself, extra params with
+/// these names are ignored.
+const RESERVED_PARAMS: &[&str] = &[
+    "response_type",
+    "client_id",
+    "redirect_uri",
+    "scope",
+    "state",
+    "code_challenge",
+    "code_challenge_method",
+    "nonce",
+    "request_uri",
+];
+
+/// Params that decide how the callback is received, the callback route and the cookies are set up
+/// for them when the context is built. They can't be set per request.
+const BUILDER_ONLY_PARAMS: &[&str] = &["response_mode"];
+
+/// Extra params of the authorization request.
+pub(crate) type AuthorizationParams = Vec<(Cow<'static, str>, String)>;
+
+/// Options for a single login flow, see [`OAuth2Context::start_challenge_with`](crate::oauth2::OAuth2Context::start_challenge_with).
+#[derive(Default, Clone, Debug)]
+pub struct ChallengeOptions {
+    pub(crate) return_to: Option<Cow<'static, str>>,
+    pub(crate) params: AuthorizationParams,
+}
+
+impl ChallengeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The path the user should be sent to after login, available with
+    /// [`AfterLoginCookies::return_to`](crate::oauth2::AfterLoginCookies::return_to). Only paths
+    /// on the same origin are allowed, other values are ignored.
+    pub fn return_to(mut self, path: impl Into<Cow<'static, str>>) -> Self {
+        self.return_to = Some(path.into());
+        self
+    }
+
+    /// Adds a param to the authorization request. It replaces the params with the same name that
+    /// were set on the [`OAuth2ContextBuilder`](crate::oauth2::OAuth2ContextBuilder).
+    /// `response_mode` can only be set on the builder and is ignored here.
+    pub fn param(mut self, name: impl Into<Cow<'static, str>>, value: impl Into<String>) -> Self {
+        self.params.push((name.into(), value.into()));
+        self
+    }
+
+    /// `prompt`, for example `consent` or `login`.
+    pub fn prompt(self, prompt: impl Into<String>) -> Self {
+        self.param("prompt", prompt)
+    }
+
+    pub fn login_hint(self, login_hint: impl Into<String>) -> Self {
+        self.param("login_hint", login_hint)
+    }
+
+    pub fn acr_values(self, acr_values: impl Into<String>) -> Self {
+        self.param("acr_values", acr_values)
+    }
+
+    pub fn audience(self, audience: impl Into<String>) -> Self {
+        self.param("audience", audience)
+    }
+
+    /// A resource indicator (RFC 8707), can be added more than once.
+    pub fn resource(self, resource: impl Into<String>) -> Self {
+        self.param("resource", resource)
+    }
+
+    /// The return path, if it is safe to redirect to.
+    pub(crate) fn safe_return_to(&self) -> Option<&str> {
+        let path = self.return_to.as_deref()?;
+
+        if is_same_origin_path(path) {
+            Some(path)
+        } else {
+            tracing::debug!("ignoring unsafe return path");
+            None
+        }
+    }
+}
+
+impl From<&'static str> for ChallengeOptions {
+    fn from(return_to: &'static str) -> Self {
+        ChallengeOptions::new().return_to(return_to)
+    }
+}
+
+impl From<String> for ChallengeOptions {
+    fn from(return_to: String) -> Self {
+        ChallengeOptions::new().return_to(return_to)
+    }
+}
+
+/// Returns true if the provider is asked to post the callback.
+pub(crate) fn is_form_post(params: &AuthorizationParams) -> bool {
+    params
+        .iter()
+        .any(|(name, value)| name == "response_mode" && value == "form_post")
+}
+
+/// The static params, without the ones that are replaced by per request params, followed by the
+/// per request params. Reserved params, and builder only params set per request, are left out.
+pub(crate) fn merge_params<'a>(
+    static_params: &'a AuthorizationParams,
+    request_params: &'a AuthorizationParams,
+) -> impl Iterator<Item = (&'a str, &'a str)> {
+    let builder_only = |name: &str| BUILDER_ONLY_PARAMS.contains(&name);
+
+    static_params
+        .iter()
+        .filter(move |(name, _)| {
+            builder_only(name) || !request_params.iter().any(|(n, _)| n == name)
+        })
+        .chain(request_params.iter().filter(move |(name, _)| {
+            if builder_only(name) {
+                tracing::debug!("ignoring per request authorization param {name}");
+            }
+            !builder_only(name)
+        }))
+        .map(|(name, value)| (name.as_ref(), value.as_str()))
+        .filter(|(name, _)| {
+            let reserved = RESERVED_PARAMS.contains(name);
+            if reserved {
+                tracing::debug!("ignoring reserved authorization param {name}");
+            }
+            !reserved
+        })
+}
+
+/// Returns true if `path` is an absolute path that stays on the same origin. Browsers treat `\`
+/// like `/`, so `//evil.com` and `/\evil.com` both point to another host.
+pub(crate) fn is_same_origin_path(path: &str) -> bool {
+    path.len() <= MAX_RETURN_PATH_LEN
+        && path.starts_with('/')
+        && !path.starts_with("//")
+        && !path.contains('\\')
+        && !path.chars().any(char::is_control)
+}
+
+#[cfg(test)]
+mod params {
+    use std::borrow::Cow;
+
+    use crate::oauth2::options::{AuthorizationParams, merge_params};
+
+    fn params(params: &[(&'static str, &str)]) -> AuthorizationParams {
+        params
+            .iter()
+            .map(|(name, value)| (Cow::Borrowed(*name), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn merge() {
+        let static_params = params(&[("prompt", "login"), ("audience", "api"), ("state", "x")]);
+        let request_params = params(&[
+            ("prompt", "consent"),
+            ("resource", "https://a.example.com"),
+            ("resource", "https://b.example.com"),
+            ("redirect_uri", "https://evil.com"),
+        ]);
+
+        let merged: Vec<_> = merge_params(&static_params, &request_params).collect();
+        assert_eq!(
+            merged,
+            [
+                ("audience", "api"),
+                ("prompt", "consent"),
+                ("resource", "https://a.example.com"),
+                ("resource", "https://b.example.com"),
+            ]
+        );
+    }
+
+    #[test]
+    fn response_mode_per_request() {
+        let static_params = params(&[]);
+        let request_params = params(&[("response_mode", "form_post")]);
+
+        let merged: Vec<_> = merge_params(&static_params, &request_params).collect();
+        assert!(merged.is_empty());
+
+        let static_params = params(&[("response_mode", "form_post")]);
+        let request_params = params(&[("response_mode", "query")]);
+
+        let merged: Vec<_> = merge_params(&static_params, &request_params).collect();
+        assert_eq!(merged, [("response_mode", "form_post")]);
+    }
+}
+
+#[cfg(test)]
+mod return_to {
+    use crate::oauth2::options::is_same_origin_path;
+
+    #[test]
+    fn same_origin() {
+        assert!(is_same_origin_path("/"));
+        assert!(is_same_origin_path("/settings"));
+        assert!(is_same_origin_path("/settings?tab=profile#email"));
+        assert!(is_same_origin_path("/a//b"));
+
+        assert!(!is_same_origin_path(""));
+        assert!(!is_same_origin_path("settings"));
+        assert!(!is_same_origin_path("//evil.com"));
+        assert!(!is_same_origin_path("/\\evil.com"));
+        assert!(!is_same_origin_path("https://evil.com"));
+        assert!(!is_same_origin_path("javascript:alert(1)"));
+        assert!(!is_same_origin_path("/\tevil"));
+        assert!(!is_same_origin_path(&format!("/{}", "a".repeat(2048))));
+    }
+}
@@ -0,0 +1,84 @@
+use oauth2::{
+    AuthType,
+    reqwest::header::ACCEPT,
+    url::{Url, form_urlencoded},
+};
+use serde::Deserialize;
+
+use crate::oauth2::{OAuth2Context, OAuth2Handler};
+
+/// The response of the pushed authorization request endpoint.
+#[derive(Deserialize)]
+struct PushedAuthorizationResponse {
+    request_uri: String,
+}
+
+impl<H: OAuth2Handler> OAuth2Context<H> {
+    /// Sends the params of `authorize_url` to the PAR endpoint (RFC 9126) and returns the
+    /// authorization url with only the client id and the returned `request_uri`.
+    pub(crate) async fn push_authorization_request(
+        &self,
+        par_url: &Url,
+        authorize_url: &Url,
+    ) -> Result<Url, String> {
+        tracing::debug!("pushing authorization request");
+        let mut params: Vec<(String, String)> = authorize_url.query_pairs().into_owned().collect();
+
+        let mut request = self
+            .0
+            .http_client
+            .post(par_url.clone())
+            .header(ACCEPT, "application/json");
+
+        match (self.0.client.auth_type(), &self.0.client_secret) {
+            // The client id and secret are form encoded before they are used as credentials,
+            // RFC 6749 section 2.3.1.
+            (AuthType::BasicAuth, Some(client_secret)) => {
+                let client_id = self.0.client.client_id().as_str();
+                request = request.basic_auth(
+                    form_urlencoded::byte_serialize(client_id.as_bytes()).collect::<String>(),
+                    Some(
+                        form_urlencoded::byte_serialize(client_secret.as_bytes())
+                            .collect::<String>(),
+                    ),
+                );
+            }
+            (_, Some(client_secret)) => {
+                params.push(("client_secret".into(), client_secret.clone()));
+            }
+            (_, None) => {}
+        }
+
+        params.extend(
+            self.client_auth_params()?
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value)),
+        );
+
+        let response = request
+            .form(&params)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let status = response.status();
+        let body = response.bytes().await.map_err(|e| e.to_string())?;
+
+        if !status.is_success() {
+            return Err(format!(
+                "endpoint returned {status}: {}",
+                String::from_utf8_lossy(&body)
+            ));
+        }
+
+        let pushed: PushedAuthorizationResponse =
+            serde_json::from_slice(&body).map_err(|e| e.to_string())?;
+
+        let mut url = self.0.client.auth_uri().url().clone();
+        url.query_pairs_mut()
+            .append_pair("client_id", self.0.client.client_id().as_str())
+            .append_pair("request_uri", &pushed.request_uri);
+
+        Ok(url)
+    }
+}
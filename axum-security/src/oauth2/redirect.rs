@@ -1,14 +1,23 @@
-use axum::{Extension, extract::Query, response::IntoResponse};
+use axum::{
+    Extension, Form,
+    extract::{Path, Query},
+    response::IntoResponse,
+};
 use cookie_monster::CookieJar;
 use oauth2::{AuthorizationCode, CsrfToken};
 use serde::Deserialize;
 
-use crate::oauth2::{OAuth2Context, OAuth2Handler};
+use crate::oauth2::{ChallengeOptions, OAuth2Context, OAuth2Handler, OAuth2Registry};
 
+/// The params of the callback, either `code` and `state` or an `error`. They are in the query, or
+/// in the form body with `response_mode=form_post`.
 #[derive(Deserialize, Debug)]
 pub struct OAuth2Params {
-    code: AuthorizationCode,
-    state: CsrfToken,
+    pub(crate) code: Option<AuthorizationCode>,
+    pub(crate) state: Option<CsrfToken>,
+    pub(crate) error: Option<String>,
+    pub(crate) error_description: Option<String>,
+    pub(crate) error_uri: Option<String>,
 }
 
 pub(crate) async fn on_redirect<H: OAuth2Handler>(
@@ -16,11 +25,42 @@ pub(crate) async fn on_redirect<H: OAuth2Handler>(
     Query(params): Query<OAuth2Params>,
     jar: CookieJar,
 ) -> impl IntoResponse {
-    context.on_redirect(jar, params.code, params.state).await
+    context.on_redirect(jar, params).await
+}
+
+pub(crate) async fn on_form_post<H: OAuth2Handler>(
+    Extension(context): Extension<OAuth2Context<H>>,
+    jar: CookieJar,
+    Form(params): Form<OAuth2Params>,
+) -> impl IntoResponse {
+    context.on_redirect(jar, params).await
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct LoginParams {
+    return_to: Option<String>,
+}
+
+impl LoginParams {
+    fn options(self) -> ChallengeOptions {
+        match self.return_to {
+            Some(return_to) => ChallengeOptions::from(return_to),
+            None => ChallengeOptions::new(),
+        }
+    }
 }
 
 pub async fn start_login<H: OAuth2Handler>(
     Extension(context): Extension<OAuth2Context<H>>,
+    Query(params): Query<LoginParams>,
+) -> impl IntoResponse {
+    context.start_challenge_with(params.options()).await
+}
+
+pub(crate) async fn start_provider_login<H: OAuth2Handler>(
+    Extension(registry): Extension<OAuth2Registry<H>>,
+    Path(provider): Path<String>,
+    Query(params): Query<LoginParams>,
 ) -> impl IntoResponse {
-    context.start_challenge().await
+    registry.start_challenge(&provider, params.options()).await
 }
@@ -0,0 +1,144 @@
+use std::{borrow::Cow, convert::Infallible, sync::Arc};
+
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::{StatusCode, request::Parts},
+    response::IntoResponse,
+};
+
+use crate::oauth2::{
+    ChallengeOptions, OAuth2BuilderError, OAuth2Context, OAuth2ContextBuilder, OAuth2Handler,
+};
+
+/// Multiple providers that share one handler. The handler can see which provider was used with
+/// [`AfterLoginCookies::provider`](crate::oauth2::AfterLoginCookies::provider).
+pub struct OAuth2Registry<H>(Arc<OAuth2RegistryInner<H>>);
+
+struct OAuth2RegistryInner<H> {
+    providers: Vec<OAuth2Context<Arc<H>>>,
+    login_path: Option<Cow<'static, str>>,
+}
+
+impl OAuth2Registry<()> {
+    pub fn builder<H: OAuth2Handler>(handler: H) -> OAuth2RegistryBuilder<H> {
+        OAuth2RegistryBuilder {
+            handler,
+            providers: Vec::new(),
+            login_path: None,
+        }
+    }
+}
+
+impl<H: OAuth2Handler> OAuth2Registry<H> {
+    pub fn get(&self, provider_name: &str) -> Option<&OAuth2Context<Arc<H>>> {
+        self.0
+            .providers
+            .iter()
+            .find(|p| p.provider_name() == provider_name)
+    }
+
+    /// The names of all providers, in the order they were added.
+    pub fn provider_names(&self) -> impl Iterator<Item = &str> {
+        self.0.providers.iter().map(|p| p.provider_name())
+    }
+
+    pub fn providers(&self) -> &[OAuth2Context<Arc<H>>] {
+        &self.0.providers
+    }
+
+    pub(crate) fn login_path(&self) -> Option<&str> {
+        self.0.login_path.as_deref()
+    }
+
+    /// Starts the login flow of `provider_name`, returns `404 Not Found` for unknown providers.
+    pub async fn start_challenge(
+        &self,
+        provider_name: &str,
+        options: impl Into<ChallengeOptions>,
+    ) -> axum::response::Response {
+        match self.get(provider_name) {
+            Some(context) => context.start_challenge_with(options).await,
+            None => StatusCode::NOT_FOUND.into_response(),
+        }
+    }
+}
+
+pub struct OAuth2RegistryBuilder<H> {
+    handler: H,
+    providers: Vec<OAuth2ContextBuilder>,
+    login_path: Option<Cow<'static, str>>,
+}
+
+impl<H: OAuth2Handler> OAuth2RegistryBuilder<H> {
+    pub fn provider(mut self, provider: OAuth2ContextBuilder) -> Self {
+        self.providers.push(provider);
+        self
+    }
+
+    /// Mounts `{login_path}/{provider}` to start the login flow of a provider.
+    pub fn login_path(mut self, path: impl Into<Cow<'static, str>>) -> Self {
+        self.login_path = Some(path.into());
+        self
+    }
+
+    pub fn build(self) -> OAuth2Registry<H> {
+        self.try_build().unwrap()
+    }
+
+    pub fn try_build(self) -> Result<OAuth2Registry<H>, OAuth2BuilderError> {
+        let handler = Arc::new(self.handler);
+        let mut providers: Vec<OAuth2Context<Arc<H>>> = Vec::new();
+
+        for builder in self.providers {
+            let context = builder.try_build(handler.clone())?;
+
+            if providers
+                .iter()
+                .any(|p| p.provider_name() == context.provider_name())
+            {
+                return Err(OAuth2BuilderError::DuplicateProvider(
+                    context.provider_name().to_string(),
+                ));
+            }
+
+            if providers
+                .iter()
+                .any(|p| p.callback_url() == context.callback_url())
+            {
+                return Err(OAuth2BuilderError::DuplicateRedirectUrl(
+                    context.callback_url().to_string(),
+                ));
+            }
+
+            providers.push(context);
+        }
+
+        let login_path = self.login_path.map(|path| match path {
+            Cow::Borrowed(path) => Cow::Borrowed(path.trim_end_matches('/')),
+            Cow::Owned(path) => Cow::Owned(path.trim_end_matches('/').to_string()),
+        });
+
+        Ok(OAuth2Registry(Arc::new(OAuth2RegistryInner {
+            providers,
+            login_path,
+        })))
+    }
+}
+
+impl<S, H> FromRequestParts<S> for OAuth2Registry<H>
+where
+    Self: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(_parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_ref(state))
+    }
+}
+
+impl<H> Clone for OAuth2Registry<H> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
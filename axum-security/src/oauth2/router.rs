@@ -1,9 +1,18 @@
 use axum::{Extension, Router, routing::MethodRouter};
 
-use crate::oauth2::{OAuth2Context, OAuth2Handler, on_redirect, start_login};
+use crate::oauth2::{
+    OAuth2Context, OAuth2Handler, OAuth2Registry,
+    logout::logout,
+    on_redirect,
+    redirect::{on_form_post, start_provider_login},
+    start_login,
+};
 
 pub trait OAuth2Ext {
     fn with_oauth2<H: OAuth2Handler>(self, context: OAuth2Context<H>) -> Self;
+
+    /// Mounts the callbacks of all providers and the `{login_path}/{provider}` route.
+    fn with_oauth2_registry<H: OAuth2Handler>(self, registry: OAuth2Registry<H>) -> Self;
 }
 
 impl<S> OAuth2Ext for Router<S>
@@ -21,10 +30,36 @@ where
             self = self.route(start_challenge_path, challenge_route);
         }
 
-        let route = MethodRouter::new()
-            .get(on_redirect::<H>)
-            .layer(Extension(context.clone()));
+        if let Some(logout_path) = context.get_logout_path() {
+            let logout_route = MethodRouter::new()
+                .post(logout::<H>)
+                .layer(Extension(context.clone()));
+
+            self = self.route(logout_path, logout_route);
+        }
+
+        let mut route = MethodRouter::new().get(on_redirect::<H>);
+        if context.uses_form_post() {
+            route = route.post(on_form_post::<H>);
+        }
+        let route = route.layer(Extension(context.clone()));
 
         self.route(context.callback_url(), route)
     }
+
+    fn with_oauth2_registry<H: OAuth2Handler>(mut self, registry: OAuth2Registry<H>) -> Self {
+        for context in registry.providers() {
+            self = self.with_oauth2(context.clone());
+        }
+
+        if let Some(login_path) = registry.login_path() {
+            let route = MethodRouter::new()
+                .get(start_provider_login::<H>)
+                .layer(Extension(registry.clone()));
+
+            self = self.route(&format!("{login_path}/{{provider}}"), route);
+        }
+
+        self
+    }
 }
@@ -0,0 +1,128 @@
+use std::{pin::Pin, sync::Arc};
+
+use cookie_monster::{CookieJar, SameSite};
+use serde::{Deserialize, Serialize};
+
+use crate::{cookie::CookieContext, oauth2::OAuth2Error};
+
+/// The state of a login flow, the CSRF token, PKCE verifier, nonce and return path. It is kept
+/// from the start of the login until the callback by a [`LoginStateStore`].
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LoginState {
+    pub(crate) csrf_token: String,
+    pub(crate) pkce_verifier: Option<String>,
+    pub(crate) nonce: Option<String>,
+    pub(crate) return_to: Option<String>,
+    pub(crate) provider_name: String,
+    pub(crate) issued: u64,
+    pub(crate) expires: u64,
+}
+
+impl LoginState {
+    /// Unix timestamp in seconds at which the login flow expires.
+    pub fn expires(&self) -> u64 {
+        self.expires
+    }
+
+    /// Checks that the flow is not expired and was started for `provider_name`.
+    pub(crate) fn verify(&self, provider_name: &str, now: u64) -> Result<(), OAuth2Error> {
+        if now < self.issued {
+            // went back in time?
+            return Err(OAuth2Error::InvalidLoginState);
+        }
+
+        if now > self.expires {
+            return Err(OAuth2Error::LoginExpired);
+        }
+
+        if self.provider_name != provider_name {
+            // the flow was started for another provider.
+            return Err(OAuth2Error::ProviderMismatch);
+        }
+
+        Ok(())
+    }
+}
+
+/// Keeps the [`LoginState`] between the start of a login and the callback. The default is an
+/// HMAC signed cookie. A [`CookieContext<LoginState>`] keeps the state in its store instead, the
+/// browser only gets the session id.
+pub trait LoginStateStore: Send + Sync + 'static {
+    /// Saves the state, cookies that the browser needs to send on the callback are added to `jar`.
+    fn save(
+        &self,
+        state: LoginState,
+        jar: &mut CookieJar,
+    ) -> impl Future<Output = Result<(), OAuth2Error>> + Send;
+
+    /// Loads and removes the state of the callback request, a state can only be taken once.
+    /// Returns [`OAuth2Error::MissingLoginState`] when there is none.
+    fn take(
+        &self,
+        jar: &mut CookieJar,
+    ) -> impl Future<Output = Result<LoginState, OAuth2Error>> + Send;
+
+    /// Returns true if the browser sends the cookies of the store with the cross-site `POST` of
+    /// a [`response_mode_form_post`](crate::oauth2::OAuth2ContextBuilder::response_mode_form_post)
+    /// callback, building the context fails otherwise.
+    fn supports_form_post(&self) -> bool {
+        true
+    }
+}
+
+impl LoginStateStore for CookieContext<LoginState> {
+    async fn save(&self, state: LoginState, jar: &mut CookieJar) -> Result<(), OAuth2Error> {
+        let cookie = self
+            .create_session(state)
+            .await
+            .map_err(OAuth2Error::LoginStateStore)?;
+
+        jar.add(cookie);
+        Ok(())
+    }
+
+    async fn take(&self, jar: &mut CookieJar) -> Result<LoginState, OAuth2Error> {
+        let session = self
+            .remove_session_jar(jar)
+            .await
+            .map_err(OAuth2Error::LoginStateStore)?;
+
+        jar.remove(self.cookie_builder().clone());
+
+        session
+            .map(|session| session.state)
+            .ok_or(OAuth2Error::MissingLoginState)
+    }
+
+    /// Only `SameSite=None` cookies are sent with cross-site posts, which requires `Secure`.
+    fn supports_form_post(&self) -> bool {
+        let cookie = self.cookie_builder().clone().build();
+        cookie.same_site() == Some(SameSite::None) && cookie.is_secure()
+    }
+}
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, OAuth2Error>> + Send + 'a>>;
+
+pub(crate) trait DynLoginStateStore: Send + Sync + 'static {
+    fn save<'a>(&'a self, state: LoginState, jar: &'a mut CookieJar) -> BoxFuture<'a, ()>;
+
+    fn take<'a>(&'a self, jar: &'a mut CookieJar) -> BoxFuture<'a, LoginState>;
+
+    fn supports_form_post(&self) -> bool;
+}
+
+impl<T: LoginStateStore> DynLoginStateStore for T {
+    fn save<'a>(&'a self, state: LoginState, jar: &'a mut CookieJar) -> BoxFuture<'a, ()> {
+        Box::pin(<T as LoginStateStore>::save(self, state, jar))
+    }
+
+    fn take<'a>(&'a self, jar: &'a mut CookieJar) -> BoxFuture<'a, LoginState> {
+        Box::pin(<T as LoginStateStore>::take(self, jar))
+    }
+
+    fn supports_form_post(&self) -> bool {
+        <T as LoginStateStore>::supports_form_post(self)
+    }
+}
+
+pub(crate) type ErasedLoginStateStore = Arc<dyn DynLoginStateStore>;
@@ -0,0 +1,163 @@
+use std::{error::Error, fmt::Display, marker::PhantomData};
+
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::{StatusCode, request::Parts},
+};
+use cookie_monster::CookieJar;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    oauth2::{OAuth2Context, OAuth2Handler, TokenResponse},
+    utils::utc_now_secs,
+};
+
+/// Access tokens that expire within this many seconds are refreshed before they are used.
+pub(crate) const REFRESH_LEEWAY_SECS: u64 = 60;
+
+/// The provider tokens of a login, kept per session by the token store, see
+/// [`OAuth2ContextBuilder::token_store`](crate::oauth2::OAuth2ContextBuilder::token_store).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderTokens {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    /// Unix timestamp in seconds at which the access token expires, if the provider returned it.
+    pub expires_at: Option<u64>,
+    /// The encoded ID token, used as `id_token_hint` on logout.
+    #[serde(default)]
+    pub id_token: Option<String>,
+}
+
+impl ProviderTokens {
+    pub fn new(token_res: &TokenResponse) -> Self {
+        ProviderTokens {
+            access_token: token_res.access_token.clone(),
+            refresh_token: token_res.refresh_token.clone(),
+            expires_at: token_res
+                .expires_in
+                .map(|expires_in| utc_now_secs() + expires_in.as_secs()),
+            id_token: token_res.id_token.clone(),
+        }
+    }
+
+    /// Returns true if the access token expires within [`REFRESH_LEEWAY_SECS`] of `now`.
+    pub(crate) fn needs_refresh(&self, now: u64) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= now + REFRESH_LEEWAY_SECS)
+    }
+}
+
+/// An error that occurred while requesting, refreshing or loading tokens.
+#[derive(Debug)]
+pub enum TokenError {
+    /// No token store is configured for this provider.
+    NoTokenStore,
+    /// The request has no token session, or it was removed from the store.
+    MissingTokens,
+    /// The access token expired and there is no refresh token.
+    Expired,
+    /// The refresh token could not be exchanged for a new access token.
+    Refresh(String),
+    /// The token store returned an error.
+    Store(Box<dyn Error + Send>),
+    /// The token endpoint rejected the client credentials grant.
+    ClientCredentials(String),
+    /// The token could not be revoked.
+    Revocation(String),
+}
+
+impl TokenError {
+    /// The status code that is returned by the [`AccessToken`] extractor.
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            TokenError::MissingTokens | TokenError::Expired | TokenError::Refresh(_) => {
+                StatusCode::UNAUTHORIZED
+            }
+            TokenError::NoTokenStore
+            | TokenError::Store(_)
+            | TokenError::ClientCredentials(_)
+            | TokenError::Revocation(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl Error for TokenError {}
+
+impl Display for TokenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenError::NoTokenStore => f.write_str("no token store is configured"),
+            TokenError::MissingTokens => f.write_str("no provider tokens found"),
+            TokenError::Expired => f.write_str("access token expired without a refresh token"),
+            TokenError::Refresh(error) => write!(f, "failed to refresh access token: {error}"),
+            TokenError::Store(error) => write!(f, "token store error: {error}"),
+            TokenError::ClientCredentials(error) => {
+                write!(f, "client credentials grant failed: {error}")
+            }
+            TokenError::Revocation(error) => write!(f, "failed to revoke token: {error}"),
+        }
+    }
+}
+
+/// Extracts a valid access token of the provider of `H` from the token store, the token is
+/// refreshed first when it is about to expire. Rejects with `401 Unauthorized` when the request
+/// has no tokens or the refresh fails.
+pub struct AccessToken<H> {
+    secret: String,
+    _handler: PhantomData<fn() -> H>,
+}
+
+impl<H> AccessToken<H> {
+    pub fn secret(&self) -> &str {
+        &self.secret
+    }
+
+    pub fn into_secret(self) -> String {
+        self.secret
+    }
+}
+
+impl<S, H> FromRequestParts<S> for AccessToken<H>
+where
+    OAuth2Context<H>: FromRef<S>,
+    H: OAuth2Handler,
+    S: Send + Sync,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let context = OAuth2Context::<H>::from_ref(state);
+        let jar = CookieJar::from_headers(&parts.headers);
+
+        match context.access_token(&jar).await {
+            Ok(secret) => Ok(AccessToken {
+                secret,
+                _handler: PhantomData,
+            }),
+            Err(error) => {
+                tracing::debug!("no access token: {error}");
+                Err(error.status_code())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod provider_tokens {
+    use crate::oauth2::tokens::ProviderTokens;
+
+    #[test]
+    fn needs_refresh() {
+        let tokens = |expires_at| ProviderTokens {
+            access_token: "token".into(),
+            refresh_token: None,
+            expires_at,
+            id_token: None,
+        };
+
+        assert!(!tokens(None).needs_refresh(1000));
+        assert!(!tokens(Some(1061)).needs_refresh(1000));
+        assert!(tokens(Some(1060)).needs_refresh(1000));
+        assert!(tokens(Some(900)).needs_refresh(1000));
+    }
+}
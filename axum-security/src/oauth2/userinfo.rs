@@ -0,0 +1,71 @@
+use std::{error::Error, fmt::Display};
+
+use oauth2::reqwest::{
+    Client as HttpClient, StatusCode,
+    header::{ACCEPT, USER_AGENT},
+};
+use serde::de::DeserializeOwned;
+
+/// An error that occurred while fetching the user of an access token.
+#[derive(Debug)]
+pub enum UserInfoError {
+    /// The request could not be sent.
+    Request(oauth2::reqwest::Error),
+    /// The provider returned a non success status code.
+    Status(StatusCode),
+    /// The response body could not be deserialized.
+    Json(serde_json::Error),
+    /// The `sub` of the response doesn't match the `sub` of the ID token.
+    #[cfg(feature = "oidc")]
+    SubjectMismatch,
+}
+
+impl Error for UserInfoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            UserInfoError::Request(error) => Some(error),
+            UserInfoError::Status(_) => None,
+            UserInfoError::Json(error) => Some(error),
+            #[cfg(feature = "oidc")]
+            UserInfoError::SubjectMismatch => None,
+        }
+    }
+}
+
+impl Display for UserInfoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UserInfoError::Request(error) => write!(f, "userinfo request failed: {error}"),
+            UserInfoError::Status(status) => write!(f, "userinfo endpoint returned {status}"),
+            UserInfoError::Json(error) => write!(f, "invalid userinfo response: {error}"),
+            #[cfg(feature = "oidc")]
+            UserInfoError::SubjectMismatch => {
+                f.write_str("userinfo sub does not match the id token")
+            }
+        }
+    }
+}
+
+/// Fetches `url` with `access_token` as bearer token and deserializes the json response.
+pub(crate) async fn fetch_userinfo<T: DeserializeOwned>(
+    http_client: &HttpClient,
+    url: &str,
+    access_token: &str,
+) -> Result<T, UserInfoError> {
+    let response = http_client
+        .get(url)
+        .bearer_auth(access_token)
+        .header(ACCEPT, "application/json")
+        // GitHub rejects requests without a user agent.
+        .header(USER_AGENT, "axum-security")
+        .send()
+        .await
+        .map_err(UserInfoError::Request)?;
+
+    if !response.status().is_success() {
+        return Err(UserInfoError::Status(response.status()));
+    }
+
+    let body = response.bytes().await.map_err(UserInfoError::Request)?;
+    serde_json::from_slice(&body).map_err(UserInfoError::Json)
+}
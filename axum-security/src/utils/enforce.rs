@@ -0,0 +1,103 @@
+use std::borrow::Cow;
+
+use http::{Method, Uri};
+
+/// Decides if a service rejects requests without a valid session before they reach the handler.
+///
+/// Paths are matched against `uri.path()`, which doesn't contain the prefix of a `nest`ed router.
+#[derive(Default, Clone)]
+pub(crate) struct Enforcement {
+    enabled: bool,
+    paths: Vec<Cow<'static, str>>,
+    path_prefixes: Vec<Cow<'static, str>>,
+    methods: Vec<Method>,
+}
+
+impl Enforcement {
+    pub(crate) fn enable(&mut self) {
+        self.enabled = true;
+    }
+
+    pub(crate) fn exempt_path(&mut self, path: impl Into<Cow<'static, str>>) {
+        self.paths.push(path.into());
+    }
+
+    pub(crate) fn exempt_path_prefix(&mut self, prefix: impl Into<Cow<'static, str>>) {
+        self.path_prefixes.push(prefix.into());
+    }
+
+    pub(crate) fn exempt_method(&mut self, method: Method) {
+        self.methods.push(method);
+    }
+
+    /// Returns true if a request without a valid session should be rejected.
+    pub(crate) fn rejects(&self, method: &Method, uri: &Uri) -> bool {
+        if !self.enabled {
+            return false;
+        }
+
+        let path = uri.path();
+
+        let exempt = self.methods.contains(method)
+            || self.paths.iter().any(|p| p == path)
+            || self.path_prefixes.iter().any(|p| is_under_prefix(path, p));
+
+        !exempt
+    }
+}
+
+/// Returns true if `path` is `prefix` or one of the paths below it. `/public` matches
+/// `/public/app.js`, but not `/publicity`.
+fn is_under_prefix(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+
+    path.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod enforcement {
+    use http::Method;
+
+    use crate::utils::enforce::Enforcement;
+
+    fn rejects(enforcement: &Enforcement, method: Method, uri: &str) -> bool {
+        enforcement.rejects(&method, &uri.parse().unwrap())
+    }
+
+    #[test]
+    fn disabled() {
+        let enforcement = Enforcement::default();
+
+        assert!(!rejects(&enforcement, Method::GET, "/"));
+    }
+
+    #[test]
+    fn exemptions() {
+        let mut enforcement = Enforcement::default();
+        enforcement.enable();
+        enforcement.exempt_path("/health");
+        enforcement.exempt_path_prefix("/assets/");
+        enforcement.exempt_method(Method::OPTIONS);
+
+        assert!(rejects(&enforcement, Method::GET, "/"));
+        assert!(rejects(&enforcement, Method::GET, "/health/db"));
+        assert!(!rejects(&enforcement, Method::GET, "/health"));
+        assert!(!rejects(&enforcement, Method::GET, "/health?verbose=true"));
+        assert!(!rejects(&enforcement, Method::GET, "/assets/app.js"));
+        assert!(!rejects(&enforcement, Method::OPTIONS, "/"));
+    }
+
+    #[test]
+    fn prefix_matches_whole_segments() {
+        let mut enforcement = Enforcement::default();
+        enforcement.enable();
+        enforcement.exempt_path_prefix("/public");
+
+        assert!(!rejects(&enforcement, Method::GET, "/public"));
+        assert!(!rejects(&enforcement, Method::GET, "/public/"));
+        assert!(!rejects(&enforcement, Method::GET, "/public/app.js"));
+        assert!(rejects(&enforcement, Method::GET, "/publicity"));
+        assert!(rejects(&enforcement, Method::GET, "/"));
+    }
+}
@@ -3,6 +3,8 @@ use std::{
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+#[cfg(any(feature = "jwt", feature = "cookie"))]
+pub(crate) mod enforce;
 #[cfg(feature = "headers")]
 pub mod headers;
 
@@ -64,8 +64,10 @@ async fn login(
     }
 }
 
-async fn logout(context: JwtContext<AccessToken>) -> Cookie {
-    context.logout_cookie()
+async fn logout(context: JwtContext<AccessToken>) -> Result<Cookie, StatusCode> {
+    context
+        .logout_cookie()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
 }
 
 #[tokio::main]
@@ -17,8 +17,6 @@ use axum_security::{
 use serde::{Deserialize, Serialize};
 use tokio::net::TcpListener;
 
-static AFTER_LOGIN_COOKIE: &str = "after-login-path";
-
 #[derive(Serialize, Clone)]
 struct User {
     id: i32,
@@ -43,9 +41,12 @@ impl OAuth2Backend {
 }
 
 impl OAuth2Handler for OAuth2Backend {
+    type Profile = ();
+
     async fn after_login(
         &self,
         res: TokenResponse,
+        _profile: (),
         cookies: &mut AfterLoginCookies<'_>,
     ) -> impl IntoResponse {
         // Fetch the user based on the access token.
@@ -55,12 +56,9 @@ impl OAuth2Handler for OAuth2Backend {
         let cookie = self.session.create_session(user).await.unwrap();
         cookies.add(cookie);
 
-        // See if we should redirect the user to a different path than "/".
-        if let Some(c) = cookies.remove(AFTER_LOGIN_COOKIE) {
-            Redirect::to(c.value())
-        } else {
-            Redirect::to("/")
-        }
+        // See if we should redirect the user to a different path than "/". The path is validated
+        // to be on the same origin, so this can't redirect to another site.
+        Redirect::to(cookies.return_to().unwrap_or("/"))
     }
 }
 
@@ -74,18 +72,11 @@ async fn login(
     Query(query): Query<NextUrl>,
 ) -> impl IntoResponse {
     // The after_login query param is the path where the user should be redirected to after the
-    // login flow is done.
-    //
-    // To do so we store the path in a cookie and after the flow is done, check for the cookie and
-    // redirect.
-    let cookie = if let Some(path) = query.after_login {
-        // Creates a cookie with the same _settings_ as the oauth2 context.
-        Some(oauth.cookie(AFTER_LOGIN_COOKIE).value(path).build())
-    } else {
-        None
-    };
-
-    (cookie, oauth.start_challenge().await)
+    // login flow is done. It is stored in the signed login cookie.
+    match query.after_login {
+        Some(path) => oauth.start_challenge_with(path).await,
+        None => oauth.start_challenge().await,
+    }
 }
 
 async fn authorized(user: CookieSession<User>) -> Json<User> {
@@ -1,5 +1,6 @@
 use std::{error::Error, time::Duration};
 
+use axum::http::StatusCode;
 use axum::{
     Json, Router,
     response::{IntoResponse, Redirect},
@@ -13,8 +14,7 @@ use axum_security::{
     },
 };
 use jiff::{Timestamp, ToSpan, Zoned};
-use reqwest::{Client, StatusCode, header::USER_AGENT};
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use tokio::net::TcpListener;
 
 #[derive(Debug, Clone, Serialize)]
@@ -27,46 +27,20 @@ struct User {
 
 struct LoginHandler {
     cookie_service: CookieContext<User>,
-    http_client: Client,
 }
 
 impl LoginHandler {
-    async fn fetch_gh_user(&self, access_token: &str) -> Result<User, StatusCode> {
-        #[derive(Debug, Deserialize)]
-        struct GithubUser {
-            id: u64,
-            login: String,
-            email: Option<String>,
-        }
-
-        let resp = self
-            .http_client
-            .get("https://api.github.com/user")
-            .header(USER_AGENT, "axum-security") // required by the github api.
-            .bearer_auth(access_token)
-            .send()
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-        let user_info = resp
-            .json::<GithubUser>()
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-        Ok(User {
-            user_id: user_info.id,
-            username: user_info.login,
-            email: user_info.email,
-            created_at: Timestamp::now(),
-        })
-    }
-
     async fn handle_login(
         &self,
-        token_res: TokenResponse,
+        gh_user: github::User,
         cookies: &mut AfterLoginCookies<'_>,
     ) -> Result<Redirect, StatusCode> {
-        let user = self.fetch_gh_user(&token_res.access_token).await?;
+        let user = User {
+            user_id: gh_user.id,
+            username: gh_user.login,
+            email: gh_user.email,
+            created_at: Timestamp::now(),
+        };
         println!("Hi, {user:?}");
 
         // Create a new session for the user.
@@ -85,12 +59,16 @@ impl LoginHandler {
 }
 
 impl OAuth2Handler for LoginHandler {
+    // Fetched from the userinfo url.
+    type Profile = github::User;
+
     async fn after_login(
         &self,
-        token_res: TokenResponse,
+        _token_res: TokenResponse,
+        gh_user: github::User,
         context: &mut AfterLoginCookies<'_>,
     ) -> impl IntoResponse {
-        self.handle_login(token_res, context).await
+        self.handle_login(gh_user, context).await
     }
 }
 
@@ -116,12 +94,12 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     let handler = LoginHandler {
         cookie_service: cookie_service.clone(),
-        http_client: Client::new(),
     };
 
     let oauth2_service = OAuth2Context::builder("test")
         .auth_url(github::AUTH_URL)
         .token_url(github::TOKEN_URL)
+        .userinfo_url(github::USERINFO_URL)
         .client_id_env("CLIENT_ID")
         .client_secret_env("CLIENT_SECRET")
         // Where the app is redirected to after login in.
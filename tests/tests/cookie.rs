@@ -0,0 +1,63 @@
+#![cfg(feature = "cookie")]
+
+use std::error::Error;
+
+use axum::{
+    Router,
+    body::Body,
+    http::{Method, Request, StatusCode, header::COOKIE},
+    routing::get,
+};
+use axum_security::cookie::{CookieContext, CookieSession, MemStore};
+use tower::Service;
+
+async fn authorized(_: CookieSession<u32>) -> StatusCode {
+    StatusCode::OK
+}
+
+async fn health() -> StatusCode {
+    StatusCode::OK
+}
+
+#[tokio::test]
+async fn cookie_require_session() -> Result<(), Box<dyn Error>> {
+    let context = CookieContext::builder()
+        .require_session()
+        .exempt_path("/health")
+        .store(MemStore::new())
+        .build::<u32>();
+
+    let cookie = context.create_session(1).await.unwrap();
+
+    let mut router = Router::new()
+        .route("/", get(authorized))
+        .route("/health", get(health))
+        .route("/open", get(health))
+        .layer(context);
+
+    let req = Request::builder()
+        .method(Method::GET)
+        .uri("/open")
+        .body(Body::empty())?;
+
+    let res = router.call(req).await?;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let req = Request::builder()
+        .method(Method::GET)
+        .uri("/health")
+        .body(Body::empty())?;
+
+    let res = router.call(req).await?;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let req = Request::builder()
+        .method(Method::GET)
+        .header(COOKIE, format!("{}={}", cookie.name(), cookie.value()))
+        .uri("/")
+        .body(Body::empty())?;
+
+    let res = router.call(req).await?;
+    assert_eq!(res.status(), StatusCode::OK);
+    Ok(())
+}